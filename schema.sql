@@ -0,0 +1,328 @@
+-- Applied by the ingester on startup, every statement must be idempotent.
+-- Rows are stored per network, rows from before the `network` columns existed are mainnet's.
+
+CREATE TABLE IF NOT EXISTS depth_intervals (
+    id SERIAL PRIMARY KEY,
+    asset_depth TEXT NOT NULL,
+    asset_price TEXT NOT NULL,
+    asset_price_usd TEXT NOT NULL,
+    end_time TEXT NOT NULL UNIQUE,
+    liquidity_units TEXT NOT NULL,
+    luvi TEXT NOT NULL,
+    members_count TEXT NOT NULL,
+    rune_depth TEXT NOT NULL,
+    start_time TEXT NOT NULL,
+    synth_supply TEXT NOT NULL,
+    synth_units TEXT NOT NULL,
+    units TEXT NOT NULL
+);
+
+-- Depths are stored per pool, rows from before the column existed are all BTC.BTC
+ALTER TABLE depth_intervals ADD COLUMN IF NOT EXISTS pool TEXT NOT NULL DEFAULT 'BTC.BTC';
+ALTER TABLE depth_intervals DROP CONSTRAINT IF EXISTS depth_intervals_end_time_key;
+ALTER TABLE depth_intervals ADD COLUMN IF NOT EXISTS network TEXT NOT NULL DEFAULT 'mainnet';
+-- When a row was stored, for Last-Modified. Rows stored before the column existed get the time it was added
+ALTER TABLE depth_intervals ADD COLUMN IF NOT EXISTS inserted_at TEXT NOT NULL DEFAULT floor(extract(epoch FROM now()))::bigint::text;
+DROP INDEX IF EXISTS depth_intervals_pool_end_time_key;
+CREATE UNIQUE INDEX IF NOT EXISTS depth_intervals_network_pool_end_time_key ON depth_intervals (network, pool, end_time);
+
+CREATE TABLE IF NOT EXISTS swap_history_intervals (
+    id SERIAL PRIMARY KEY,
+    average_slip TEXT NOT NULL,
+    end_time TEXT NOT NULL UNIQUE,
+    from_trade_average_slip TEXT NOT NULL,
+    from_trade_count TEXT NOT NULL,
+    from_trade_fees TEXT NOT NULL,
+    from_trade_volume TEXT NOT NULL,
+    from_trade_volume_usd TEXT NOT NULL,
+    rune_price_usd TEXT NOT NULL,
+    start_time TEXT NOT NULL,
+    synth_mint_average_slip TEXT NOT NULL,
+    synth_mint_count TEXT NOT NULL,
+    synth_mint_fees TEXT NOT NULL,
+    synth_mint_volume TEXT NOT NULL,
+    synth_mint_volume_usd TEXT NOT NULL,
+    synth_redeem_average_slip TEXT NOT NULL,
+    synth_redeem_count TEXT NOT NULL,
+    synth_redeem_fees TEXT NOT NULL,
+    synth_redeem_volume TEXT NOT NULL,
+    synth_redeem_volume_usd TEXT NOT NULL,
+    to_asset_average_slip TEXT NOT NULL,
+    to_asset_count TEXT NOT NULL,
+    to_asset_fees TEXT NOT NULL,
+    to_asset_volume TEXT NOT NULL,
+    to_asset_volume_usd TEXT NOT NULL,
+    to_rune_average_slip TEXT NOT NULL,
+    to_rune_count TEXT NOT NULL,
+    to_rune_fees TEXT NOT NULL,
+    to_rune_volume TEXT NOT NULL,
+    to_rune_volume_usd TEXT NOT NULL,
+    total_count TEXT NOT NULL,
+    total_fees TEXT NOT NULL,
+    total_volume TEXT NOT NULL,
+    total_volume_usd TEXT NOT NULL
+);
+
+-- Swaps are stored per pool, the network-wide series lives under the 'all' pool
+ALTER TABLE swap_history_intervals ADD COLUMN IF NOT EXISTS pool TEXT NOT NULL DEFAULT 'all';
+ALTER TABLE swap_history_intervals DROP CONSTRAINT IF EXISTS swap_history_intervals_end_time_key;
+ALTER TABLE swap_history_intervals ADD COLUMN IF NOT EXISTS network TEXT NOT NULL DEFAULT 'mainnet';
+ALTER TABLE swap_history_intervals ADD COLUMN IF NOT EXISTS inserted_at TEXT NOT NULL DEFAULT floor(extract(epoch FROM now()))::bigint::text;
+DROP INDEX IF EXISTS swap_history_intervals_pool_end_time_key;
+CREATE UNIQUE INDEX IF NOT EXISTS swap_history_intervals_network_pool_end_time_key ON swap_history_intervals (network, pool, end_time);
+
+CREATE TABLE IF NOT EXISTS earning_intervals (
+    id SERIAL PRIMARY KEY,
+    avg_node_count TEXT NOT NULL,
+    block_rewards TEXT NOT NULL,
+    bonding_earnings TEXT NOT NULL,
+    earnings TEXT NOT NULL,
+    end_time TEXT NOT NULL UNIQUE,
+    liquidity_earnings TEXT NOT NULL,
+    liquidity_fees TEXT NOT NULL,
+    rune_price_usd TEXT NOT NULL,
+    start_time TEXT NOT NULL
+);
+
+ALTER TABLE earning_intervals ADD COLUMN IF NOT EXISTS network TEXT NOT NULL DEFAULT 'mainnet';
+ALTER TABLE earning_intervals ADD COLUMN IF NOT EXISTS inserted_at TEXT NOT NULL DEFAULT floor(extract(epoch FROM now()))::bigint::text;
+ALTER TABLE earning_intervals DROP CONSTRAINT IF EXISTS earning_intervals_end_time_key;
+CREATE UNIQUE INDEX IF NOT EXISTS earning_intervals_network_end_time_key ON earning_intervals (network, end_time);
+
+CREATE TABLE IF NOT EXISTS pools (
+    id SERIAL PRIMARY KEY,
+    interval_id INTEGER NOT NULL REFERENCES earning_intervals (id),
+    asset_liquidity_fees TEXT,
+    earnings TEXT,
+    pool TEXT,
+    rewards TEXT,
+    rune_liquidity_fees TEXT,
+    saver_earning TEXT,
+    total_liquidity_fees_rune TEXT
+);
+
+CREATE TABLE IF NOT EXISTS rune_pool_intervals (
+    id SERIAL PRIMARY KEY,
+    count TEXT NOT NULL,
+    end_time TEXT NOT NULL UNIQUE,
+    start_time TEXT NOT NULL,
+    units TEXT NOT NULL
+);
+
+ALTER TABLE rune_pool_intervals ADD COLUMN IF NOT EXISTS network TEXT NOT NULL DEFAULT 'mainnet';
+ALTER TABLE rune_pool_intervals ADD COLUMN IF NOT EXISTS inserted_at TEXT NOT NULL DEFAULT floor(extract(epoch FROM now()))::bigint::text;
+ALTER TABLE rune_pool_intervals DROP CONSTRAINT IF EXISTS rune_pool_intervals_end_time_key;
+CREATE UNIQUE INDEX IF NOT EXISTS rune_pool_intervals_network_end_time_key ON rune_pool_intervals (network, end_time);
+
+CREATE TABLE IF NOT EXISTS tvl_intervals (
+    id SERIAL PRIMARY KEY,
+    end_time TEXT NOT NULL UNIQUE,
+    pools_depth TEXT NOT NULL,
+    rune_price_usd TEXT,
+    start_time TEXT NOT NULL,
+    total_value_bonded TEXT,
+    total_value_locked TEXT,
+    total_value_locked_usd TEXT,
+    total_value_pooled TEXT NOT NULL
+);
+
+ALTER TABLE tvl_intervals ADD COLUMN IF NOT EXISTS network TEXT NOT NULL DEFAULT 'mainnet';
+ALTER TABLE tvl_intervals ADD COLUMN IF NOT EXISTS inserted_at TEXT NOT NULL DEFAULT floor(extract(epoch FROM now()))::bigint::text;
+ALTER TABLE tvl_intervals DROP CONSTRAINT IF EXISTS tvl_intervals_end_time_key;
+CREATE UNIQUE INDEX IF NOT EXISTS tvl_intervals_network_end_time_key ON tvl_intervals (network, end_time);
+
+CREATE TABLE IF NOT EXISTS liquidity_change_intervals (
+    id SERIAL PRIMARY KEY,
+    add_asset_liquidity_volume TEXT NOT NULL,
+    add_liquidity_count TEXT NOT NULL,
+    add_liquidity_volume TEXT NOT NULL,
+    add_liquidity_volume_usd TEXT NOT NULL,
+    add_rune_liquidity_volume TEXT NOT NULL,
+    end_time TEXT NOT NULL,
+    impermanent_loss_protection_paid TEXT,
+    net TEXT NOT NULL,
+    pool TEXT NOT NULL,
+    rune_price_usd TEXT NOT NULL,
+    start_time TEXT NOT NULL,
+    withdraw_asset_volume TEXT NOT NULL,
+    withdraw_count TEXT NOT NULL,
+    withdraw_rune_volume TEXT NOT NULL,
+    withdraw_volume TEXT NOT NULL,
+    withdraw_volume_usd TEXT NOT NULL,
+    UNIQUE (pool, end_time)
+);
+
+ALTER TABLE liquidity_change_intervals ADD COLUMN IF NOT EXISTS network TEXT NOT NULL DEFAULT 'mainnet';
+ALTER TABLE liquidity_change_intervals ADD COLUMN IF NOT EXISTS inserted_at TEXT NOT NULL DEFAULT floor(extract(epoch FROM now()))::bigint::text;
+ALTER TABLE liquidity_change_intervals DROP CONSTRAINT IF EXISTS liquidity_change_intervals_pool_end_time_key;
+CREATE UNIQUE INDEX IF NOT EXISTS liquidity_change_intervals_network_pool_end_time_key ON liquidity_change_intervals (network, pool, end_time);
+
+CREATE TABLE IF NOT EXISTS saver_intervals (
+    id SERIAL PRIMARY KEY,
+    end_time TEXT NOT NULL,
+    pool TEXT NOT NULL,
+    savers_count TEXT NOT NULL,
+    savers_depth TEXT NOT NULL,
+    savers_units TEXT NOT NULL,
+    start_time TEXT NOT NULL,
+    UNIQUE (pool, end_time)
+);
+
+ALTER TABLE saver_intervals ADD COLUMN IF NOT EXISTS network TEXT NOT NULL DEFAULT 'mainnet';
+ALTER TABLE saver_intervals ADD COLUMN IF NOT EXISTS inserted_at TEXT NOT NULL DEFAULT floor(extract(epoch FROM now()))::bigint::text;
+ALTER TABLE saver_intervals DROP CONSTRAINT IF EXISTS saver_intervals_pool_end_time_key;
+CREATE UNIQUE INDEX IF NOT EXISTS saver_intervals_network_pool_end_time_key ON saver_intervals (network, pool, end_time);
+
+CREATE TABLE IF NOT EXISTS pool_snapshots (
+    id SERIAL PRIMARY KEY,
+    annual_percentage_rate TEXT NOT NULL,
+    asset TEXT NOT NULL,
+    asset_depth TEXT NOT NULL,
+    asset_price TEXT NOT NULL,
+    asset_price_usd TEXT NOT NULL,
+    liquidity_units TEXT NOT NULL,
+    pool_apy TEXT NOT NULL,
+    rune_depth TEXT NOT NULL,
+    savers_apr TEXT,
+    savers_depth TEXT,
+    savers_units TEXT,
+    snapshot_time TEXT NOT NULL,
+    status TEXT NOT NULL,
+    synth_supply TEXT NOT NULL,
+    synth_units TEXT NOT NULL,
+    units TEXT NOT NULL,
+    volume24h TEXT NOT NULL,
+    average_slip TEXT,
+    swap_count TEXT,
+    swap_volume TEXT,
+    total_fees TEXT,
+    unique_member_count TEXT,
+    unique_swapper_count TEXT,
+    UNIQUE (asset, snapshot_time)
+);
+
+ALTER TABLE pool_snapshots ADD COLUMN IF NOT EXISTS network TEXT NOT NULL DEFAULT 'mainnet';
+ALTER TABLE pool_snapshots DROP CONSTRAINT IF EXISTS pool_snapshots_asset_snapshot_time_key;
+CREATE UNIQUE INDEX IF NOT EXISTS pool_snapshots_network_asset_snapshot_time_key ON pool_snapshots (network, asset, snapshot_time);
+
+CREATE TABLE IF NOT EXISTS actions (
+    id SERIAL PRIMARY KEY,
+    action_type TEXT NOT NULL,
+    addresses TEXT[] NOT NULL,
+    affiliate_fee TEXT,
+    date TEXT NOT NULL,
+    height TEXT NOT NULL,
+    in_coins TEXT NOT NULL,
+    is_streaming_swap BOOLEAN NOT NULL,
+    liquidity_fee TEXT,
+    liquidity_units TEXT,
+    network_fees TEXT NOT NULL,
+    out_coins TEXT NOT NULL,
+    pools TEXT[] NOT NULL,
+    status TEXT NOT NULL,
+    streaming_swap_meta TEXT,
+    swap_slip TEXT,
+    tx_id TEXT NOT NULL,
+    UNIQUE (tx_id, action_type, date)
+);
+
+ALTER TABLE actions ADD COLUMN IF NOT EXISTS network TEXT NOT NULL DEFAULT 'mainnet';
+ALTER TABLE actions DROP CONSTRAINT IF EXISTS actions_tx_id_action_type_date_key;
+CREATE UNIQUE INDEX IF NOT EXISTS actions_network_tx_id_action_type_date_key ON actions (network, tx_id, action_type, date);
+
+CREATE INDEX IF NOT EXISTS actions_pools_idx ON actions USING GIN (pools);
+CREATE INDEX IF NOT EXISTS actions_addresses_idx ON actions USING GIN (addresses);
+
+CREATE TABLE IF NOT EXISTS network_snapshots (
+    id SERIAL PRIMARY KEY,
+    active_node_count TEXT NOT NULL,
+    block_reward TEXT NOT NULL,
+    bond_reward TEXT NOT NULL,
+    bonding_apy TEXT NOT NULL,
+    liquidity_apy TEXT NOT NULL,
+    next_churn_height TEXT NOT NULL,
+    pool_reward TEXT NOT NULL,
+    pool_share_factor TEXT,
+    snapshot_time TEXT NOT NULL UNIQUE,
+    standby_node_count TEXT NOT NULL,
+    total_active_bond TEXT NOT NULL,
+    total_pooled_rune TEXT NOT NULL,
+    total_reserve TEXT NOT NULL,
+    total_standby_bond TEXT NOT NULL
+);
+
+ALTER TABLE network_snapshots ADD COLUMN IF NOT EXISTS network TEXT NOT NULL DEFAULT 'mainnet';
+ALTER TABLE network_snapshots DROP CONSTRAINT IF EXISTS network_snapshots_snapshot_time_key;
+CREATE UNIQUE INDEX IF NOT EXISTS network_snapshots_network_snapshot_time_key ON network_snapshots (network, snapshot_time);
+
+CREATE TABLE IF NOT EXISTS churns (
+    id SERIAL PRIMARY KEY,
+    date TEXT NOT NULL,
+    height TEXT NOT NULL UNIQUE
+);
+
+ALTER TABLE churns ADD COLUMN IF NOT EXISTS network TEXT NOT NULL DEFAULT 'mainnet';
+ALTER TABLE churns DROP CONSTRAINT IF EXISTS churns_height_key;
+CREATE UNIQUE INDEX IF NOT EXISTS churns_network_height_key ON churns (network, height);
+
+CREATE TABLE IF NOT EXISTS alert_rules (
+    id SERIAL PRIMARY KEY,
+    condition TEXT NOT NULL,
+    dataset TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    field TEXT NOT NULL,
+    last_evaluated_at TEXT,
+    last_value TEXT,
+    name TEXT NOT NULL,
+    pool TEXT,
+    state TEXT NOT NULL DEFAULT 'ok',
+    threshold TEXT NOT NULL,
+    webhook_url TEXT,
+    window_hours INTEGER NOT NULL DEFAULT 24
+);
+
+ALTER TABLE alert_rules ADD COLUMN IF NOT EXISTS network TEXT NOT NULL DEFAULT 'mainnet';
+
+-- One event per rule, state and interval, so a rule never fires twice for the same data
+CREATE TABLE IF NOT EXISTS alert_events (
+    id SERIAL PRIMARY KEY,
+    baseline TEXT,
+    delivery_status TEXT,
+    end_time TEXT NOT NULL,
+    rule_id INTEGER NOT NULL REFERENCES alert_rules (id) ON DELETE CASCADE,
+    state TEXT NOT NULL,
+    triggered_at TEXT NOT NULL,
+    value TEXT,
+    UNIQUE (rule_id, state, end_time)
+);
+
+-- Only the SHA-256 of a key is stored, `prefix` identifies it in listings
+CREATE TABLE IF NOT EXISTS api_keys (
+    id SERIAL PRIMARY KEY,
+    burst INTEGER NOT NULL,
+    created_at TEXT NOT NULL,
+    daily_quota BIGINT,
+    key_hash TEXT NOT NULL UNIQUE,
+    last_used_at TEXT,
+    name TEXT NOT NULL,
+    prefix TEXT NOT NULL,
+    rate_per_minute INTEGER NOT NULL,
+    revoked BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE TABLE IF NOT EXISTS api_key_usage (
+    key_id INTEGER NOT NULL REFERENCES api_keys (id) ON DELETE CASCADE,
+    day TEXT NOT NULL,
+    requests BIGINT NOT NULL,
+    PRIMARY KEY (key_id, day)
+);
+
+-- Short-lived tokens for browsers, which cannot send headers when opening /ws or /events
+CREATE TABLE IF NOT EXISTS stream_tokens (
+    token_hash TEXT PRIMARY KEY,
+    key_id INTEGER NOT NULL REFERENCES api_keys (id) ON DELETE CASCADE,
+    expires_at TEXT NOT NULL
+);
+
+-- The key that created a rule, only that key sees and deletes it
+ALTER TABLE alert_rules ADD COLUMN IF NOT EXISTS api_key_id INTEGER REFERENCES api_keys (id) ON DELETE CASCADE;
@@ -0,0 +1,82 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use chrono::Utc;
+use tokio::time::MissedTickBehavior;
+use tokio_postgres::Client;
+
+use crate::config::{env_or, Network};
+use crate::db::{establish_connection, fetch_action_cursor, insert_actions, AppError};
+use crate::events::publish_error;
+use crate::midgard::{MidgardClient, ACTIONS_PAGE_SIZE};
+use crate::shutdown::Shutdown;
+
+const DEFAULT_ACTIONS_INTERVAL_SECS: u64 = 60;
+/// Pending actions up to this far behind the newest stored one are fetched again until they settle
+const PENDING_WINDOW_SECS: i64 = 86400;
+
+/// Pages through `network`'s new Midgard actions each `ACTIONS_INTERVAL_SECS` until a shutdown is requested.
+pub async fn run_action_ingester(network: &Network, midgard: MidgardClient, mut shutdown: Shutdown) -> Result<(), AppError> {
+    let mut client = establish_connection().await?;
+    let network = network.name.as_str();
+
+    let cadence = Duration::from_secs(env_or("ACTIONS_INTERVAL_SECS", DEFAULT_ACTIONS_INTERVAL_SECS).max(1));
+    let mut ticker = tokio::time::interval(cadence);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = shutdown.wait() => break,
+        }
+
+        match sync_actions(&mut client, &midgard, network, &mut shutdown).await {
+            Ok(stored) => println!("{} actions synced successfully! ({} new or updated)", network, stored),
+            Err(e) => {
+                eprintln!("Failed to sync {} actions: {}", network, e);
+                if client.is_closed() {
+                    client = establish_connection().await?;
+                }
+                publish_error(&client, network, "action ingester", &e).await;
+            }
+        }
+    }
+
+    println!("{} action ingester stopped.", network);
+    Ok(())
+}
+
+/// Pages forward from the cursor, committing each page on its own. Midgard pages oldest
+/// first from `fromTimestamp`, so an interrupted sync resumes where its last commit ended.
+async fn sync_actions(client: &mut Client, midgard: &MidgardClient, network: &str, shutdown: &mut Shutdown) -> Result<u64, AppError> {
+    let from_timestamp = match fetch_action_cursor(client, network, PENDING_WINDOW_SECS).await? {
+        Some(date) => date / 1_000_000_000,
+        None => Utc::now().timestamp() - 3600,
+    };
+
+    let mut stored = 0;
+    let mut newer_page_token: Option<String> = None;
+    loop {
+        let (mut actions, newer) = tokio::select! {
+            page = midgard.fetch_actions_page(from_timestamp, newer_page_token.as_deref()) => page?,
+            _ = shutdown.wait() => return Ok(stored),
+        };
+        let fetched = actions.len();
+
+        // A page can repeat an action while new ones arrive, keep the first copy of each
+        let mut seen = HashSet::new();
+        actions.retain(|a| seen.insert((a.tx_id.clone(), a.action_type.clone(), a.date.clone())));
+        if !actions.is_empty() {
+            let tx = client.transaction().await?;
+            stored += insert_actions(&tx, network, &actions).await?;
+            tx.commit().await?;
+        }
+
+        match newer {
+            Some(token) if fetched == ACTIONS_PAGE_SIZE => newer_page_token = Some(token),
+            _ => break,
+        }
+    }
+
+    Ok(stored)
+}
@@ -0,0 +1,528 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use axum::{extract::{Path, Query}, Extension, Json};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
+use tokio_postgres::{Client, Row};
+
+use crate::analytics::{dataset, is_numeric_field};
+use crate::api::quote;
+use crate::config::DEFAULT_NETWORK;
+use crate::db::{establish_connection, AppError};
+use crate::keys::Caller;
+use crate::model::{AlertEvent, AlertRule, NewAlertRule};
+
+/// `above`/`below` compare the latest value with the threshold, `pct_drop`/`pct_rise`
+/// compare its change in percent against the value `window_hours` earlier.
+const CONDITIONS: [&str; 4] = ["above", "below", "pct_drop", "pct_rise"];
+
+/// Evaluates the alert rules after each ingestion batch and delivers state changes to
+/// the rule's webhook, or to `ALERT_WEBHOOK_URL` when the rule has none.
+pub struct AlertEvaluator {
+    http: reqwest::Client,
+    default_webhook: Option<String>,
+}
+
+/// Client for webhooks, redirects are not followed so a public webhook cannot send the
+/// request on to a private host. `pinned` connects a host to the address that was checked.
+fn webhook_client(pinned: Option<(&str, SocketAddr)>) -> Result<reqwest::Client, reqwest::Error> {
+    let builder = reqwest::Client::builder()
+        .user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+        .timeout(Duration::from_secs(10))
+        .redirect(reqwest::redirect::Policy::none());
+    match pinned {
+        Some((host, addr)) => builder.resolve(host, addr).build(),
+        None => builder.build(),
+    }
+}
+
+/// Whether `ip` is reachable from the internet, rather than a loopback, private,
+/// link-local, shared or otherwise special address of the server's own network.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+/// The host of a rule's webhook and the address to deliver to. Only http(s) URLs whose
+/// host resolves to public addresses are accepted, so rules cannot reach into the
+/// server's network. Checked when a rule is created and again before each delivery.
+pub async fn check_webhook(url: &str) -> Result<(String, SocketAddr), String> {
+    let url = reqwest::Url::parse(url).map_err(|e| format!("webhookUrl is not a URL: {}", e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("webhookUrl must be an http or https URL".to_string());
+    }
+    let Some(host) = url.host_str().map(|host| host.trim_start_matches('[').trim_end_matches(']').to_string()) else {
+        return Err("webhookUrl needs a host".to_string());
+    };
+    let lowercase = host.to_ascii_lowercase();
+    if lowercase == "localhost" || lowercase.ends_with(".localhost") {
+        return Err("webhookUrl must not point at a local or private address".to_string());
+    }
+
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), port))
+        .await
+        .map_err(|_| format!("webhookUrl host {} does not resolve", host))?
+        .collect();
+    match addrs.first() {
+        Some(addr) if addrs.iter().all(|addr| is_public(addr.ip())) => Ok((host, *addr)),
+        Some(_) => Err("webhookUrl must not point at a local or private address".to_string()),
+        None => Err(format!("webhookUrl host {} does not resolve", host)),
+    }
+}
+
+impl AlertEvaluator {
+    pub fn from_env() -> Result<AlertEvaluator, reqwest::Error> {
+        Ok(AlertEvaluator {
+            http: webhook_client(None)?,
+            default_webhook: std::env::var("ALERT_WEBHOOK_URL").ok().filter(|url| !url.is_empty()),
+        })
+    }
+
+    /// Evaluates every enabled rule of `network`. Failures are logged, they never stop ingestion.
+    pub async fn evaluate(&self, client: &Client, network: &str) {
+        let rows = match client.query("SELECT * FROM alert_rules WHERE enabled AND network = $1 ORDER BY id", &[&network]).await {
+            Ok(rows) => rows,
+            Err(e) => {
+                eprintln!("Failed to fetch alert rules: {}", e);
+                return;
+            }
+        };
+
+        for rule in rows.iter().map(alert_rule_from_row) {
+            if let Err(e) = self.evaluate_rule(client, &rule).await {
+                eprintln!("Failed to evaluate alert rule {} ({}): {}", rule.id, rule.name, e);
+            }
+        }
+    }
+
+    async fn evaluate_rule(&self, client: &Client, rule: &AlertRule) -> Result<(), AppError> {
+        // Dataset and field were validated when the rule was created
+        let Some(dataset) = dataset(&rule.dataset) else {
+            return Ok(());
+        };
+        let pool_filter = dataset
+            .default_pool
+            .map(|default| format!("pool = {} AND", quote(rule.pool.as_deref().unwrap_or(default))))
+            .unwrap_or_default();
+        let pool_filter = format!("network = {} AND {}", quote(&rule.network), pool_filter);
+
+        let query = format!(
+            "WITH latest AS (
+                SELECT end_time::bigint AS end_time, {field}::numeric::float8 AS value FROM {source}
+                WHERE {pool_filter} TRUE ORDER BY end_time::bigint DESC LIMIT 1
+            )
+            SELECT latest.end_time, latest.value,
+                (SELECT {field}::numeric::float8 FROM {source}
+                WHERE {pool_filter} end_time::bigint <= latest.end_time - {window} ORDER BY end_time::bigint DESC LIMIT 1) AS baseline
+            FROM latest",
+            field = rule.field,
+            source = dataset.source,
+            pool_filter = pool_filter,
+            window = i64::from(rule.window_hours) * 3600,
+        );
+        let Some(row) = client.query_opt(&query, &[]).await? else {
+            return Ok(());
+        };
+
+        let end_time: i64 = row.get("end_time");
+        let value: Option<f64> = row.get("value");
+        let baseline: Option<f64> = row.get("baseline");
+        let threshold: f64 = rule.threshold.parse().unwrap_or(f64::NAN);
+
+        let triggered = match (rule.condition.as_str(), value, baseline) {
+            ("above", Some(value), _) => value > threshold,
+            ("below", Some(value), _) => value < threshold,
+            ("pct_drop", Some(value), Some(baseline)) if baseline != 0.0 => {
+                let drop = (baseline - value) / baseline.abs() * 100.0;
+                drop > 0.0 && drop >= threshold
+            }
+            ("pct_rise", Some(value), Some(baseline)) if baseline != 0.0 => {
+                let rise = (value - baseline) / baseline.abs() * 100.0;
+                rise > 0.0 && rise >= threshold
+            }
+            _ => false,
+        };
+
+        let now = Utc::now().timestamp().to_string();
+        let value = value.map(|v| v.to_string());
+        client
+            .execute("UPDATE alert_rules SET last_value = $2, last_evaluated_at = $3 WHERE id = $1", &[&rule.id, &value, &now])
+            .await?;
+
+        // Only changes of state are recorded and delivered
+        let (event_state, rule_state) = match (rule.state.as_str(), triggered) {
+            ("firing", false) => ("resolved", "ok"),
+            ("firing", true) | (_, false) => return Ok(()),
+            (_, true) => ("firing", "firing"),
+        };
+
+        let event = client
+            .query_opt(
+                "WITH event AS (
+                    INSERT INTO alert_events (baseline, end_time, rule_id, state, triggered_at, value)
+                    VALUES ($1, $2, $3, $4, $5, $6)
+                    ON CONFLICT (rule_id, state, end_time) DO NOTHING
+                    RETURNING *
+                ), rule AS (
+                    UPDATE alert_rules SET state = $7 WHERE id = $3
+                )
+                SELECT * FROM event",
+                &[&baseline.map(|b| b.to_string()), &end_time.to_string(), &rule.id, &event_state, &now, &value, &rule_state],
+            )
+            .await?;
+        let Some(event) = event.as_ref().map(alert_event_from_row) else {
+            return Ok(());
+        };
+        println!("Alert {} ({}) is {}", rule.id, rule.name, event.state);
+
+        let rule = AlertRule {
+            last_evaluated_at: Some(now),
+            last_value: value,
+            state: rule_state.to_string(),
+            ..rule.clone()
+        };
+        let delivery_status = self.deliver(&rule, &event).await;
+        client
+            .execute("UPDATE alert_events SET delivery_status = $2 WHERE id = $1", &[&event.id, &delivery_status])
+            .await?;
+
+        Ok(())
+    }
+
+    /// POSTs the event with its rule to the webhook, returning what to record as its delivery
+    /// status. The operator's `ALERT_WEBHOOK_URL` may be private, a rule's webhook may not.
+    async fn deliver(&self, rule: &AlertRule, event: &AlertEvent) -> String {
+        let (url, http) = match (&rule.webhook_url, &self.default_webhook) {
+            (Some(url), _) => {
+                let pinned = match check_webhook(url).await {
+                    Ok(pinned) => pinned,
+                    Err(e) => {
+                        eprintln!("Refused to deliver alert {} to {}: {}", rule.id, url, e);
+                        return format!("error: {}", e);
+                    }
+                };
+                match webhook_client(Some((&pinned.0, pinned.1))) {
+                    Ok(http) => (url, http),
+                    Err(e) => return format!("error: {}", e),
+                }
+            }
+            (None, Some(url)) => (url, self.http.clone()),
+            (None, None) => return "no webhook".to_string(),
+        };
+
+        let payload = json!({ "rule": rule, "event": event });
+        match http.post(url).json(&payload).send().await {
+            Ok(response) => response.status().to_string(),
+            Err(e) => {
+                eprintln!("Failed to deliver alert {} to {}: {}", rule.id, url, e);
+                format!("error: {}", e)
+            }
+        }
+    }
+}
+
+fn alert_rule_from_row(row: &Row) -> AlertRule {
+    AlertRule {
+        condition: row.get("condition"),
+        dataset: row.get("dataset"),
+        enabled: row.get("enabled"),
+        field: row.get("field"),
+        id: row.get("id"),
+        last_evaluated_at: row.get("last_evaluated_at"),
+        last_value: row.get("last_value"),
+        name: row.get("name"),
+        network: row.get("network"),
+        pool: row.get("pool"),
+        state: row.get("state"),
+        threshold: row.get("threshold"),
+        webhook_url: row.get("webhook_url"),
+        window_hours: row.get("window_hours"),
+    }
+}
+
+fn alert_event_from_row(row: &Row) -> AlertEvent {
+    AlertEvent {
+        baseline: row.get("baseline"),
+        delivery_status: row.get("delivery_status"),
+        end_time: row.get("end_time"),
+        id: row.get("id"),
+        rule_id: row.get("rule_id"),
+        state: row.get("state"),
+        triggered_at: row.get("triggered_at"),
+        value: row.get("value"),
+    }
+}
+
+/// The key id rules are owned by, `None` for unauthenticated requests when keys are not required.
+fn owner(caller: Option<Extension<Caller>>) -> Option<i32> {
+    caller.map(|Extension(caller)| caller.key_id)
+}
+
+/// The alert rules created with the calling key.
+pub async fn get_alert_rules(caller: Option<Extension<Caller>>) -> Json<serde_json::Value> {
+    let owner = owner(caller);
+    match establish_connection().await {
+        Ok(client) => {
+            let rows = match client.query("SELECT * FROM alert_rules WHERE api_key_id IS NOT DISTINCT FROM $1 ORDER BY id", &[&owner]).await {
+                Ok(rows) => rows,
+                Err(e) => {
+                    eprintln!("Failed to fetch alert rules: {}", e);
+                    return Json(json!({ "error": "Failed to fetch alert rules" }));
+                }
+            };
+            let rules: Vec<AlertRule> = rows.iter().map(alert_rule_from_row).collect();
+
+            Json(json!({ "data": rules }))
+        }
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {}", e);
+            Json(json!({ "error": "Failed to connect to database" }))
+        }
+    }
+}
+
+pub async fn create_alert_rule(caller: Option<Extension<Caller>>, Json(rule): Json<NewAlertRule>) -> Json<serde_json::Value> {
+    let Some(dataset) = dataset(&rule.dataset) else {
+        return Json(json!({ "error": "dataset must be one of depth, swap, earnings, earnings/pools, rune, tvl, liquidity, savers" }));
+    };
+    if !CONDITIONS.contains(&rule.condition.as_str()) {
+        return Json(json!({ "error": "condition must be one of above, below, pct_drop, pct_rise" }));
+    }
+    if rule.threshold.parse::<f64>().is_err() {
+        return Json(json!({ "error": "threshold must be a number" }));
+    }
+    let window_hours = rule.window_hours.unwrap_or(24);
+    let network = rule.network.as_deref().unwrap_or(DEFAULT_NETWORK);
+    if window_hours < 1 {
+        return Json(json!({ "error": "windowHours must be at least 1" }));
+    }
+    if let Some(url) = &rule.webhook_url {
+        if let Err(e) = check_webhook(url).await {
+            return Json(json!({ "error": e }));
+        }
+    }
+    let owner = owner(caller);
+
+    match establish_connection().await {
+        Ok(client) => {
+            match is_numeric_field(&client, dataset.table, &rule.field).await {
+                Ok(true) => {}
+                Ok(false) => return Json(json!({ "error": format!("{} is not a numeric field of {}", rule.field, rule.dataset) })),
+                Err(e) => {
+                    eprintln!("Failed to look up the fields of {}: {}", dataset.table, e);
+                    return Json(json!({ "error": "Failed to create alert rule" }));
+                }
+            }
+
+            let row = client
+                .query_one(
+                    "INSERT INTO alert_rules (api_key_id, condition, dataset, field, name, network, pool, threshold, webhook_url, window_hours)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING *",
+                    &[&owner, &rule.condition, &rule.dataset, &rule.field, &rule.name, &network, &rule.pool, &rule.threshold, &rule.webhook_url, &window_hours],
+                )
+                .await;
+            match row {
+                Ok(row) => Json(json!({ "data": alert_rule_from_row(&row) })),
+                Err(e) => {
+                    eprintln!("Failed to create alert rule: {}", e);
+                    Json(json!({ "error": "Failed to create alert rule" }))
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {}", e);
+            Json(json!({ "error": "Failed to connect to database" }))
+        }
+    }
+}
+
+/// Deletes a rule created with the calling key, other keys' rules count as not found.
+pub async fn delete_alert_rule(caller: Option<Extension<Caller>>, Path(id): Path<i32>) -> Json<serde_json::Value> {
+    let owner = owner(caller);
+    match establish_connection().await {
+        Ok(client) => match client.execute("DELETE FROM alert_rules WHERE id = $1 AND api_key_id IS NOT DISTINCT FROM $2", &[&id, &owner]).await {
+            Ok(deleted) => Json(json!({ "deleted": deleted })),
+            Err(e) => {
+                eprintln!("Failed to delete alert rule {}: {}", id, e);
+                Json(json!({ "error": "Failed to delete alert rule" }))
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {}", e);
+            Json(json!({ "error": "Failed to connect to database" }))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AlertEventParams {
+    rule_id: Option<i32>,
+    page: Option<i64>,
+    limit: Option<i64>,
+}
+
+/// Events of the rules created with the calling key.
+pub async fn get_alert_events(caller: Option<Extension<Caller>>, Query(params): Query<AlertEventParams>) -> Json<serde_json::Value> {
+    let owner = owner(caller);
+    match establish_connection().await {
+        Ok(client) => {
+            let limit = params.limit.unwrap_or(400).max(0);
+            let offset = (params.page.unwrap_or(1).max(1) - 1).saturating_mul(limit);
+            let rows = client
+                .query(
+                    "SELECT alert_events.* FROM alert_events JOIN alert_rules ON alert_rules.id = alert_events.rule_id
+                    WHERE alert_rules.api_key_id IS NOT DISTINCT FROM $4 AND ($1::int IS NULL OR rule_id = $1)
+                    ORDER BY alert_events.id DESC LIMIT $2 OFFSET $3",
+                    &[&params.rule_id, &limit, &offset, &owner],
+                )
+                .await;
+            let rows = match rows {
+                Ok(rows) => rows,
+                Err(e) => {
+                    eprintln!("Failed to fetch alert events: {}", e);
+                    return Json(json!({ "error": "Failed to fetch alert events" }));
+                }
+            };
+            let events: Vec<AlertEvent> = rows.iter().map(alert_event_from_row).collect();
+
+            Json(json!({ "data": events }))
+        }
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {}", e);
+            Json(json!({ "error": "Failed to connect to database" }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{routing::post, Router};
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    fn rule(webhook_url: Option<String>) -> AlertRule {
+        AlertRule {
+            condition: "above".to_string(),
+            dataset: "depth".to_string(),
+            enabled: true,
+            field: "rune_depth".to_string(),
+            id: 7,
+            last_evaluated_at: Some("1700003600".to_string()),
+            last_value: Some("12".to_string()),
+            name: "BTC depth".to_string(),
+            network: "mainnet".to_string(),
+            pool: Some("BTC.BTC".to_string()),
+            state: "firing".to_string(),
+            threshold: "10".to_string(),
+            webhook_url,
+            window_hours: 24,
+        }
+    }
+
+    fn event() -> AlertEvent {
+        AlertEvent {
+            baseline: None,
+            delivery_status: None,
+            end_time: "1700003600".to_string(),
+            id: 3,
+            rule_id: 7,
+            state: "firing".to_string(),
+            triggered_at: "1700003601".to_string(),
+            value: Some("12".to_string()),
+        }
+    }
+
+    /// A webhook on 127.0.0.1 passing each body it receives to the returned channel.
+    async fn receiver() -> (String, mpsc::UnboundedReceiver<serde_json::Value>) {
+        let (sender, received) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/hook",
+            post(move |Json(body): Json<serde_json::Value>| async move {
+                sender.send(body).unwrap();
+            }),
+        );
+        let server = axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let url = format!("http://{}/hook", server.local_addr());
+        tokio::spawn(server);
+        (url, received)
+    }
+
+    #[tokio::test]
+    async fn delivers_the_event_with_its_rule_to_the_default_webhook() {
+        let (url, mut received) = receiver().await;
+        let alerts = AlertEvaluator { http: webhook_client(None).unwrap(), default_webhook: Some(url) };
+
+        assert_eq!(alerts.deliver(&rule(None), &event()).await, "200 OK");
+        let body = received.recv().await.unwrap();
+        assert_eq!(body["rule"]["id"], 7);
+        assert_eq!(body["rule"]["state"], "firing");
+        assert_eq!(body["event"]["endTime"], "1700003600");
+        assert_eq!(body["event"]["state"], "firing");
+    }
+
+    #[tokio::test]
+    async fn refuses_to_deliver_to_a_private_rule_webhook() {
+        let (url, mut received) = receiver().await;
+        let alerts = AlertEvaluator { http: webhook_client(None).unwrap(), default_webhook: None };
+
+        let status = alerts.deliver(&rule(Some(url)), &event()).await;
+        assert!(status.starts_with("error: "), "{}", status);
+        assert!(received.try_recv().is_err());
+        assert_eq!(alerts.deliver(&rule(None), &event()).await, "no webhook");
+    }
+
+    #[tokio::test]
+    async fn accepts_only_public_http_webhooks() {
+        for url in [
+            "ftp://93.184.216.34/hook",
+            "http://localhost:8080/hook",
+            "http://api.localhost/hook",
+            "http://127.0.0.1/hook",
+            "http://10.1.2.3/hook",
+            "http://172.16.0.1/hook",
+            "http://192.168.1.1/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://100.64.0.1/hook",
+            "http://0.0.0.0/hook",
+            "http://[::1]/hook",
+            "http://[fd00::1]/hook",
+            "http://[fe80::1]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+            "not a url",
+        ] {
+            assert!(check_webhook(url).await.is_err(), "{} was accepted", url);
+        }
+
+        let (host, addr) = check_webhook("https://93.184.216.34/hook").await.unwrap();
+        assert_eq!(host, "93.184.216.34");
+        assert_eq!(addr, "93.184.216.34:443".parse().unwrap());
+        assert!(check_webhook("http://[2606:2800:220:1::1]:8080/hook").await.is_ok());
+    }
+}
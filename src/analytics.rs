@@ -0,0 +1,664 @@
+use std::str::FromStr;
+
+use axum::{extract::Query, Json};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::json;
+use tokio_postgres::Client;
+
+use crate::api::{last_inserted_at, quote};
+use crate::config::DEFAULT_NETWORK;
+use crate::db::establish_connection;
+use crate::model::ALL_POOLS;
+
+const HOURS_PER_YEAR: f64 = 8760.0;
+/// Base units in one RUNE or asset.
+const BASE_UNITS: Decimal = Decimal::from_parts(100_000_000, 0, 0, false, 0);
+
+#[derive(Deserialize)]
+pub struct ApyParams {
+    pool: Option<String>,
+    window: Option<String>,
+    network: Option<String>,
+}
+
+/// Fee APR, reward APR and compounded APY of a pool over the last 7, 30 or 90 days
+/// of stored earnings and depth, ending at the latest stored earnings interval. RUNE's
+/// price is that of the latest interval, for `units=usd`.
+/// Responses are cached by `cache::ResponseCache`, which drops them when depth or
+/// earnings are ingested.
+pub async fn get_pool_apy(Query(params): Query<ApyParams>) -> Json<serde_json::Value> {
+    let pool = params.pool.unwrap_or_else(|| "BTC.BTC".to_string());
+    let window = params.window.unwrap_or_else(|| "30d".to_string());
+    let network = params.network.unwrap_or_else(|| DEFAULT_NETWORK.to_string());
+    let days: i64 = match window.as_str() {
+        "7d" => 7,
+        "30d" => 30,
+        "90d" => 90,
+        _ => return Json(json!({ "error": "window must be one of 7d, 30d, 90d" })),
+    };
+
+    let client = match establish_connection().await {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {}", e);
+            return Json(json!({ "error": "Failed to connect to database" }));
+        }
+    };
+
+    let query = "
+        WITH bounds AS (
+            SELECT MAX(end_time::bigint) AS window_end, MAX(end_time::bigint) - $2 AS window_start FROM earning_intervals WHERE network = $3
+        )
+        SELECT e.hours, e.fees::text AS fees, e.rewards::text AS rewards, d.average_pool_value::text AS average_pool_value,
+               bounds.window_start::text AS window_start, bounds.window_end::text AS window_end,
+               (SELECT rune_price_usd FROM earning_intervals WHERE network = $3 AND end_time::bigint = bounds.window_end LIMIT 1) AS rune_price_usd
+        FROM bounds,
+            LATERAL (
+                SELECT COUNT(*) AS hours, SUM(p.total_liquidity_fees_rune::numeric) AS fees, SUM(p.rewards::numeric) AS rewards
+                FROM pools p JOIN earning_intervals ei ON ei.id = p.interval_id
+                WHERE ei.network = $3 AND p.pool = $1 AND ei.end_time::bigint > bounds.window_start
+            ) e,
+            LATERAL (
+                SELECT AVG(2 * rune_depth::numeric) AS average_pool_value
+                FROM depth_intervals
+                WHERE network = $3 AND pool = $1 AND end_time::bigint > bounds.window_start AND end_time::bigint <= bounds.window_end
+            ) d";
+
+    let row = match client.query_one(query, &[&pool, &(days * 86400), &network]).await {
+        Ok(row) => row,
+        Err(e) => {
+            eprintln!("Failed to compute APY for {}: {}", pool, e);
+            return Json(json!({ "error": "Failed to compute APY" }));
+        }
+    };
+
+    let hours: i64 = row.get("hours");
+    let fees: Option<String> = row.get("fees");
+    let rewards: Option<String> = row.get("rewards");
+    let average_pool_value: Option<String> = row.get("average_pool_value");
+
+    let parse = |value: &Option<String>| value.as_deref().and_then(|v| v.parse::<f64>().ok());
+    let (fees_rune, rewards_rune, pool_value) = match (parse(&fees), parse(&rewards), parse(&average_pool_value)) {
+        (Some(fees), Some(rewards), Some(pool_value)) if hours > 0 && pool_value > 0.0 => (fees, rewards, pool_value),
+        _ => return Json(json!({ "error": format!("No stored earnings and depth for {} in the last {}", pool, window) })),
+    };
+
+    // Annualised over the hours actually stored, so a partially filled window is not understated
+    let annualise = HOURS_PER_YEAR / hours as f64;
+    let fee_apr = fees_rune / pool_value * annualise;
+    let reward_apr = rewards_rune / pool_value * annualise;
+    let apr = fee_apr + reward_apr;
+    let apy = (1.0 + apr / 365.0).powf(365.0) - 1.0;
+
+    Json(json!({
+        "data": {
+            "pool": pool,
+            "window": window,
+            "windowStart": row.get::<_, Option<String>>("window_start"),
+            "windowEnd": row.get::<_, Option<String>>("window_end"),
+            "hours": hours.to_string(),
+            "feesRune": fees,
+            "rewardsRune": rewards,
+            "averagePoolValueRune": average_pool_value,
+            "runePriceUSD": row.get::<_, Option<String>>("rune_price_usd"),
+            "feeAPR": fee_apr.to_string(),
+            "rewardAPR": reward_apr.to_string(),
+            "APR": apr.to_string(),
+            "APY": apy.to_string(),
+        },
+        "formula": {
+            "poolValue": "2 * depth_intervals.rune_depth, averaged over the window (both sides of the pool are worth the same in RUNE)",
+            "feeAPR": "sum(pools.total_liquidity_fees_rune) / poolValue * 8760 / hours",
+            "rewardAPR": "sum(pools.rewards) / poolValue * 8760 / hours",
+            "APR": "feeAPR + rewardAPR",
+            "APY": "(1 + APR / 365) ^ 365 - 1, compounded daily",
+            "hours": "number of stored hourly earnings intervals of the pool in the window",
+        },
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct LpPerformanceParams {
+    pool: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    /// Liquidity units of the position, apart from the `units` amounts are served in
+    lp_units: Option<String>,
+    network: Option<String>,
+}
+
+/// A pool as stored for one depth interval, as far as LP performance needs it.
+#[derive(Debug, Clone, Copy)]
+struct PoolState {
+    units: Decimal,
+    rune_depth: Decimal,
+    asset_depth: Decimal,
+    /// RUNE per unit of the asset
+    asset_price: Decimal,
+    luvi: Decimal,
+}
+
+/// A position of some liquidity units at one interval, against holding what it held when it was entered.
+#[derive(Debug)]
+struct LpPosition {
+    share: Decimal,
+    rune_amount: Decimal,
+    asset_amount: Decimal,
+    value_rune: Decimal,
+    hodl_value_rune: Decimal,
+    /// What the position earned since it was entered: its value times the part of LUVI
+    /// (liquidity unit value index) that grew since then
+    fees_accrued_rune: Decimal,
+    /// The position had it earned nothing, so it only moved with the price
+    value_without_fees_rune: Decimal,
+    /// Loss against holding from the price move alone, fees not included
+    impermanent_loss: Decimal,
+    /// Fees as a part of what holding would be worth
+    fee_return: Decimal,
+    /// `impermanent_loss + fee_return`, the position against holding
+    return_vs_hodl: Decimal,
+}
+
+/// `units` in `pool`, entered at `entry`. `None` when either has no units or no price.
+fn lp_position(units: Decimal, pool: &PoolState, entry: &PoolState) -> Option<LpPosition> {
+    if pool.units <= Decimal::ZERO || entry.units <= Decimal::ZERO || pool.asset_price <= Decimal::ZERO {
+        return None;
+    }
+
+    let share = units / pool.units;
+    let rune_amount = share * pool.rune_depth;
+    let asset_amount = share * pool.asset_depth;
+    let value_rune = rune_amount + asset_amount * pool.asset_price;
+
+    let entry_share = units / entry.units;
+    let hodl_value_rune = entry_share * entry.rune_depth + entry_share * entry.asset_depth * pool.asset_price;
+    if hodl_value_rune <= Decimal::ZERO {
+        return None;
+    }
+
+    // A position's value grows with LUVI through fees and rewards, and with the square root of the price
+    let fees_accrued_rune = if pool.luvi > Decimal::ZERO && entry.luvi > Decimal::ZERO {
+        value_rune - value_rune * entry.luvi / pool.luvi
+    } else {
+        Decimal::ZERO
+    };
+    let value_without_fees_rune = value_rune - fees_accrued_rune;
+
+    Some(LpPosition {
+        share,
+        rune_amount,
+        asset_amount,
+        value_rune,
+        hodl_value_rune,
+        fees_accrued_rune,
+        value_without_fees_rune,
+        impermanent_loss: value_without_fees_rune / hodl_value_rune - Decimal::ONE,
+        fee_return: fees_accrued_rune / hodl_value_rune,
+        return_vs_hodl: value_rune / hodl_value_rune - Decimal::ONE,
+    })
+}
+
+/// Value over time of an LP position of `lp_units` liquidity units, against holding the
+/// RUNE and asset it was worth at `from`, with impermanent loss and fees told apart.
+/// RUNE and asset amounts are in 1e8 base units, USD values in dollars.
+pub async fn get_lp_performance(Query(params): Query<LpPerformanceParams>) -> Json<serde_json::Value> {
+    let pool = params.pool.unwrap_or_else(|| "BTC.BTC".to_string());
+    let units = match params.lp_units.as_deref().and_then(|u| Decimal::from_str(u).ok()) {
+        Some(units) if units > Decimal::ZERO => units,
+        _ => return Json(json!({ "error": "lp_units must be a positive number of liquidity units" })),
+    };
+    let from = params.from.as_deref().and_then(|t| t.parse::<i64>().ok()).unwrap_or(0);
+    let to = params.to.as_deref().and_then(|t| t.parse::<i64>().ok()).unwrap_or(i64::MAX);
+    let network = params.network.as_deref().unwrap_or(DEFAULT_NETWORK);
+
+    let client = match establish_connection().await {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {}", e);
+            return Json(json!({ "error": "Failed to connect to database" }));
+        }
+    };
+
+    let rows = match client
+        .query(
+            "SELECT start_time, end_time, asset_depth, asset_price, asset_price_usd, luvi, rune_depth, units FROM depth_intervals
+            WHERE network = $4 AND pool = $1 AND start_time::bigint >= $2 AND end_time::bigint <= $3 ORDER BY end_time::bigint ASC",
+            &[&pool, &from, &to, &network],
+        )
+        .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            eprintln!("Failed to fetch depth history for {}: {}", pool, e);
+            return Json(json!({ "error": "Failed to compute LP performance" }));
+        }
+    };
+
+    let number = |row: &tokio_postgres::Row, column: &str| {
+        row.get::<_, Option<String>>(column).and_then(|v| Decimal::from_str(&v).ok()).unwrap_or(Decimal::ZERO)
+    };
+
+    let states: Vec<PoolState> = rows
+        .iter()
+        .map(|row| PoolState {
+            units: number(row, "units"),
+            rune_depth: number(row, "rune_depth"),
+            asset_depth: number(row, "asset_depth"),
+            asset_price: number(row, "asset_price"),
+            luvi: number(row, "luvi"),
+        })
+        .collect();
+    // The position is entered at the first interval with units and a price
+    let entry = states.iter().find(|state| state.units > Decimal::ZERO && state.asset_price > Decimal::ZERO).copied();
+    // What the position was worth at entry, in RUNE and USD
+    let mut entry_value: Option<(Decimal, Decimal)> = None;
+    let mut intervals = Vec::with_capacity(rows.len());
+
+    for (row, state) in rows.iter().zip(&states) {
+        let Some(position) = entry.and_then(|entry| lp_position(units, state, &entry)) else {
+            continue;
+        };
+        let asset_price_usd = number(row, "asset_price_usd");
+        let rune_price_usd = asset_price_usd / state.asset_price;
+        let value_usd = position.value_rune / BASE_UNITS * rune_price_usd;
+        let (entry_value_rune, entry_value_usd) = *entry_value.get_or_insert((position.value_rune, value_usd));
+
+        intervals.push(json!({
+            "startTime": row.get::<_, String>("start_time"),
+            "endTime": row.get::<_, String>("end_time"),
+            "poolShare": position.share.normalize().to_string(),
+            "runeAmount": position.rune_amount.normalize().to_string(),
+            "assetAmount": position.asset_amount.normalize().to_string(),
+            "valueRune": position.value_rune.normalize().to_string(),
+            "valueAsset": (position.value_rune / state.asset_price).normalize().to_string(),
+            "valueUSD": value_usd.normalize().to_string(),
+            "hodlValueRune": position.hodl_value_rune.normalize().to_string(),
+            "hodlValueUSD": (position.hodl_value_rune / BASE_UNITS * rune_price_usd).normalize().to_string(),
+            "valueWithoutFeesRune": position.value_without_fees_rune.normalize().to_string(),
+            "impermanentLoss": position.impermanent_loss.normalize().to_string(),
+            "feesAccruedRune": position.fees_accrued_rune.normalize().to_string(),
+            "feeReturn": position.fee_return.normalize().to_string(),
+            "returnVsHodl": position.return_vs_hodl.normalize().to_string(),
+            "pnlRune": (position.value_rune - entry_value_rune).normalize().to_string(),
+            "pnlUSD": (value_usd - entry_value_usd).normalize().to_string(),
+            "runePriceUSD": rune_price_usd.normalize().to_string(),
+            "assetPriceUSD": asset_price_usd.normalize().to_string(),
+        }));
+    }
+
+    Json(json!({
+        "data": intervals,
+        "formula": {
+            "poolShare": "lp_units / depth_intervals.units",
+            "valueRune": "poolShare * (rune_depth + asset_depth * asset_price)",
+            "valueUSD": "valueRune / 1e8 * asset_price_usd / asset_price",
+            "hodlValueRune": "first interval's RUNE and asset amounts, valued at the current asset_price",
+            "feesAccruedRune": "valueRune * (1 - first luvi / luvi)",
+            "valueWithoutFeesRune": "valueRune - feesAccruedRune",
+            "impermanentLoss": "valueWithoutFeesRune / hodlValueRune - 1, from the price move alone",
+            "feeReturn": "feesAccruedRune / hodlValueRune",
+            "returnVsHodl": "valueRune / hodlValueRune - 1, impermanentLoss + feeReturn",
+            "pnlRune": "valueRune - first valueRune, likewise in USD",
+        },
+    }))
+}
+
+/// A stored hourly series that indicators can be computed over.
+pub struct Dataset {
+    /// Rows with `network`, `end_time`, `inserted_at`, the dataset's fields and `pool` when it is per pool
+    pub source: &'static str,
+    /// Table whose columns are the fields that can be requested
+    pub table: &'static str,
+    pub default_pool: Option<&'static str>,
+}
+
+/// Datasets by the name of the route serving them.
+pub fn dataset(name: &str) -> Option<Dataset> {
+    let (source, table, default_pool) = match name {
+        "depth" => ("depth_intervals", "depth_intervals", Some("BTC.BTC")),
+        "swap" => ("swap_history_intervals", "swap_history_intervals", Some(ALL_POOLS)),
+        "earnings" => ("earning_intervals", "earning_intervals", None),
+        "earnings/pools" => (
+            "(SELECT p.*, ei.network, ei.end_time, ei.inserted_at FROM pools p JOIN earning_intervals ei ON ei.id = p.interval_id) pool_earnings",
+            "pools",
+            Some("BTC.BTC"),
+        ),
+        "rune" => ("rune_pool_intervals", "rune_pool_intervals", None),
+        "tvl" => ("tvl_intervals", "tvl_intervals", None),
+        "liquidity" => ("liquidity_change_intervals", "liquidity_change_intervals", Some("BTC.BTC")),
+        "savers" => ("saver_intervals", "saver_intervals", Some("BTC.BTC")),
+        _ => return None,
+    };
+    Some(Dataset { source, table, default_pool })
+}
+
+/// Whether `field` is one of the amount columns of `table`, the only columns that
+/// may be spliced into the SQL.
+pub async fn is_numeric_field(client: &Client, table: &str, field: &str) -> Result<bool, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            "SELECT 1 FROM information_schema.columns
+            WHERE table_name = $1 AND column_name = $2 AND data_type = 'text'
+            AND column_name NOT IN ('network', 'pool', 'start_time', 'end_time', 'inserted_at', 'pools_depth')",
+            &[&table, &field],
+        )
+        .await?;
+    Ok(row.is_some())
+}
+
+#[derive(Deserialize)]
+pub struct IndicatorParams {
+    dataset: Option<String>,
+    field: Option<String>,
+    indicator: Option<String>,
+    window: Option<usize>,
+    interval: Option<String>,
+    aggregate: Option<String>,
+    pool: Option<String>,
+    start_time: Option<String>,
+    end_time: Option<String>,
+    order: Option<String>,
+    page: Option<usize>,
+    limit: Option<usize>,
+    network: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Indicator {
+    Sma,
+    Ema,
+    Sum,
+    Stddev,
+    PctChange,
+    Zscore,
+}
+
+impl Indicator {
+    fn parse(name: &str) -> Option<Indicator> {
+        Some(match name {
+            "sma" => Indicator::Sma,
+            "ema" => Indicator::Ema,
+            "sum" => Indicator::Sum,
+            "stddev" => Indicator::Stddev,
+            "pct_change" => Indicator::PctChange,
+            "zscore" => Indicator::Zscore,
+            _ => return None,
+        })
+    }
+}
+
+/// Applies `indicator` over the trailing `window` values of a series, `None` where
+/// there is not enough history yet.
+fn compute_indicator(indicator: Indicator, values: &[Option<f64>], window: usize) -> Vec<Option<f64>> {
+    let trailing = |i: usize| -> Option<Vec<f64>> {
+        if i + 1 < window {
+            return None;
+        }
+        values[i + 1 - window..=i].iter().copied().collect()
+    };
+    let mean = |w: &[f64]| w.iter().sum::<f64>() / w.len() as f64;
+    let stddev = |w: &[f64]| {
+        if w.len() < 2 {
+            return None;
+        }
+        let m = mean(w);
+        Some((w.iter().map(|v| (v - m).powi(2)).sum::<f64>() / (w.len() - 1) as f64).sqrt())
+    };
+
+    match indicator {
+        Indicator::Sma => (0..values.len()).map(|i| trailing(i).map(|w| mean(&w))).collect(),
+        Indicator::Sum => (0..values.len()).map(|i| trailing(i).map(|w| w.iter().sum())).collect(),
+        Indicator::Stddev => (0..values.len()).map(|i| trailing(i).and_then(|w| stddev(&w))).collect(),
+        Indicator::Zscore => (0..values.len())
+            .map(|i| {
+                let w = trailing(i)?;
+                let sd = stddev(&w).filter(|sd| *sd > 0.0)?;
+                Some((w[w.len() - 1] - mean(&w)) / sd)
+            })
+            .collect(),
+        Indicator::PctChange => (0..values.len())
+            .map(|i| match (i.checked_sub(window).and_then(|j| values[j]), values[i]) {
+                (Some(previous), Some(current)) if previous != 0.0 => Some((current / previous - 1.0) * 100.0),
+                _ => None,
+            })
+            .collect(),
+        Indicator::Ema => {
+            // Seeded with the SMA of the first full window, gaps keep the last average
+            let alpha = 2.0 / (window as f64 + 1.0);
+            let mut previous: Option<f64> = None;
+            (0..values.len())
+                .map(|i| {
+                    previous = match (previous, values[i]) {
+                        (Some(p), Some(v)) => Some(alpha * v + (1.0 - alpha) * p),
+                        (Some(p), None) => Some(p),
+                        (None, _) => trailing(i).map(|w| mean(&w)),
+                    };
+                    previous
+                })
+                .collect()
+        }
+    }
+}
+
+/// SMA, EMA, rolling sum, rolling standard deviation, percent change or z-score of
+/// any numeric field of a stored dataset. `window` counts rows of the chosen `interval`,
+/// and the indicator is computed over the full history before filtering and paging.
+pub async fn get_indicator(Query(params): Query<IndicatorParams>) -> Json<serde_json::Value> {
+    let dataset_name = params.dataset.as_deref().unwrap_or("depth");
+    let Some(dataset) = dataset(dataset_name) else {
+        return Json(json!({ "error": "dataset must be one of depth, swap, earnings, earnings/pools, rune, tvl, liquidity, savers" }));
+    };
+    let indicator_name = params.indicator.as_deref().unwrap_or("sma");
+    let Some(indicator) = Indicator::parse(indicator_name) else {
+        return Json(json!({ "error": "indicator must be one of sma, ema, sum, stddev, pct_change, zscore" }));
+    };
+    let window = params.window.unwrap_or(24);
+    if window == 0 {
+        return Json(json!({ "error": "window must be at least 1" }));
+    }
+    let bucket = match params.interval.as_deref().unwrap_or("hour") {
+        "hour" => None,
+        interval @ ("day" | "week" | "month" | "year") => Some(interval),
+        _ => return Json(json!({ "error": "interval must be one of hour, day, week, month, year" })),
+    };
+    let field = params.field.as_deref().unwrap_or_default();
+
+    let client = match establish_connection().await {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {}", e);
+            return Json(json!({ "error": "Failed to connect to database" }));
+        }
+    };
+
+    match is_numeric_field(&client, dataset.table, field).await {
+        Ok(true) => {}
+        Ok(false) => return Json(json!({ "error": format!("{} is not a numeric field of {}", field, dataset_name) })),
+        Err(e) => {
+            eprintln!("Failed to look up the fields of {}: {}", dataset.table, e);
+            return Json(json!({ "error": "Failed to compute indicator" }));
+        }
+    }
+
+    let value = match (bucket, params.aggregate.as_deref().unwrap_or("last")) {
+        (None, _) => format!("{}::numeric", field),
+        (Some(_), "last") => format!("(array_agg({}::numeric ORDER BY end_time::bigint DESC))[1]", field),
+        (Some(_), aggregate @ ("sum" | "avg" | "min" | "max")) => format!("{}({}::numeric)", aggregate, field),
+        (Some(_), _) => return Json(json!({ "error": "aggregate must be one of last, sum, avg, min, max" })),
+    };
+
+    let pool = dataset.default_pool.map(|default| params.pool.clone().unwrap_or_else(|| default.to_string()));
+    let mut filters = vec![format!("network = {}", quote(params.network.as_deref().unwrap_or(DEFAULT_NETWORK)))];
+    if pool.is_some() {
+        filters.push("pool = $1".to_string());
+    }
+    if let Some(end_time) = params.end_time.as_deref().and_then(|t| t.parse::<i64>().ok()) {
+        filters.push(format!("end_time::bigint <= {}", end_time));
+    }
+    let filters = format!("WHERE {}", filters.join(" AND "));
+
+    let query = match bucket {
+        None => format!(
+            "SELECT end_time::bigint AS time, {}::float8 AS value, inserted_at FROM {} {} ORDER BY time",
+            value, dataset.source, filters
+        ),
+        Some(bucket) => format!(
+            "SELECT MAX(end_time::bigint) AS time, {}::float8 AS value, MAX(inserted_at::bigint)::text AS inserted_at FROM {} {} GROUP BY date_trunc('{}', to_timestamp(end_time::bigint)) ORDER BY time",
+            value, dataset.source, filters, bucket
+        ),
+    };
+
+    println!("Generated SQL Query: {}", query);
+
+    let rows = match &pool {
+        Some(pool) => client.query(&query, &[pool]).await,
+        None => client.query(&query, &[]).await,
+    };
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(e) => {
+            eprintln!("Failed to fetch {} of {}: {}", field, dataset_name, e);
+            return Json(json!({ "error": "Failed to compute indicator" }));
+        }
+    };
+
+    let times: Vec<i64> = rows.iter().map(|row| row.get("time")).collect();
+    let values: Vec<Option<f64>> = rows.iter().map(|row| row.get("value")).collect();
+    let results = compute_indicator(indicator, &values, window);
+
+    let start_time = params.start_time.as_deref().and_then(|t| t.parse::<i64>().ok()).unwrap_or(i64::MIN);
+    let mut points: Vec<serde_json::Value> = times
+        .iter()
+        .zip(values.iter().zip(&results))
+        .filter(|(time, _)| **time >= start_time)
+        .map(|(time, (value, result))| {
+            json!({
+                "endTime": time.to_string(),
+                "value": value.map(|v| v.to_string()),
+                "indicator": result.map(|r| r.to_string()),
+            })
+        })
+        .collect();
+
+    if !params.order.as_deref().is_some_and(|order| order.eq_ignore_ascii_case("asc")) {
+        points.reverse();
+    }
+    let limit = params.limit.unwrap_or(400);
+    let page = params.page.unwrap_or(1).max(1);
+    let points: Vec<serde_json::Value> = points.into_iter().skip((page - 1).saturating_mul(limit)).take(limit).collect();
+
+    Json(json!({
+        "dataset": dataset_name,
+        "field": field,
+        "indicator": indicator_name,
+        "window": window,
+        "pool": pool,
+        "data": points,
+        // Of every row read, the indicator depends on all of them
+        "lastInsertedAt": last_inserted_at(&rows),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    /// A pool of `units` with the given depths, its LUVI derived from them.
+    fn pool(units: &str, rune_depth: &str, asset_depth: &str, luvi: &str) -> PoolState {
+        let (rune_depth, asset_depth) = (decimal(rune_depth), decimal(asset_depth));
+        PoolState { units: decimal(units), rune_depth, asset_depth, asset_price: rune_depth / asset_depth, luvi: decimal(luvi) }
+    }
+
+    #[test]
+    fn lp_position_at_entry_matches_holding() {
+        let entry = pool("100", "1000", "10", "1");
+        let position = lp_position(decimal("10"), &entry, &entry).unwrap();
+
+        assert_eq!(position.share, decimal("0.1"));
+        assert_eq!(position.rune_amount, decimal("100"));
+        assert_eq!(position.asset_amount, decimal("1"));
+        assert_eq!(position.value_rune, decimal("200"));
+        assert_eq!(position.hodl_value_rune, decimal("200"));
+        assert!(position.fees_accrued_rune.is_zero());
+        assert!(position.impermanent_loss.is_zero());
+        assert!(position.return_vs_hodl.is_zero());
+    }
+
+    #[test]
+    fn impermanent_loss_of_a_price_move_without_fees() {
+        // The price goes from 100 to 400 RUNE at the same sqrt(rune_depth * asset_depth)
+        let entry = pool("100", "1000", "10", "1");
+        let later = pool("100", "2000", "5", "1");
+        let position = lp_position(decimal("10"), &later, &entry).unwrap();
+
+        assert_eq!(position.value_rune, decimal("400"));
+        assert_eq!(position.hodl_value_rune, decimal("500"));
+        // 2 * sqrt(4) / (1 + 4) - 1
+        assert_eq!(position.impermanent_loss, decimal("-0.2"));
+        assert!(position.fees_accrued_rune.is_zero());
+        assert_eq!(position.return_vs_hodl, position.impermanent_loss);
+    }
+
+    #[test]
+    fn fees_are_reported_apart_from_impermanent_loss() {
+        // The same price move, with LUVI up 10% from fees
+        let entry = pool("100", "1000", "10", "1");
+        let later = pool("100", "2200", "5.5", "1.1");
+        let position = lp_position(decimal("10"), &later, &entry).unwrap();
+
+        assert_eq!(position.value_rune, decimal("440"));
+        assert_eq!(position.fees_accrued_rune.round_dp(20), decimal("40"));
+        assert_eq!(position.value_without_fees_rune.round_dp(20), decimal("400"));
+        assert_eq!(position.impermanent_loss.round_dp(20), decimal("-0.2"));
+        assert_eq!(position.fee_return.round_dp(20), decimal("0.08"));
+        assert_eq!(position.return_vs_hodl, decimal("-0.12"));
+    }
+
+    #[test]
+    fn lp_position_needs_units_and_a_price() {
+        let entry = pool("100", "1000", "10", "1");
+        let empty = PoolState { units: Decimal::ZERO, ..entry };
+        let unpriced = PoolState { asset_price: Decimal::ZERO, ..entry };
+
+        assert!(lp_position(decimal("10"), &empty, &entry).is_none());
+        assert!(lp_position(decimal("10"), &entry, &empty).is_none());
+        assert!(lp_position(decimal("10"), &unpriced, &entry).is_none());
+    }
+
+    fn series(values: &[f64]) -> Vec<Option<f64>> {
+        values.iter().copied().map(Some).collect()
+    }
+
+    #[test]
+    fn sma_averages_the_trailing_window() {
+        assert_eq!(compute_indicator(Indicator::Sma, &series(&[1.0, 2.0, 3.0, 4.0, 5.0]), 3), vec![None, None, Some(2.0), Some(3.0), Some(4.0)]);
+
+        // A gap leaves every window containing it without a value
+        let values = vec![Some(1.0), Some(2.0), None, Some(4.0), Some(5.0), Some(6.0)];
+        assert_eq!(compute_indicator(Indicator::Sma, &values, 2), vec![None, Some(1.5), None, None, Some(4.5), Some(5.5)]);
+    }
+
+    #[test]
+    fn ema_is_seeded_with_the_first_sma() {
+        // alpha = 2 / (3 + 1) = 0.5
+        assert_eq!(
+            compute_indicator(Indicator::Ema, &series(&[2.0, 4.0, 6.0, 8.0, 12.0]), 3),
+            vec![None, None, Some(4.0), Some(6.0), Some(9.0)]
+        );
+
+        let values = vec![Some(2.0), Some(4.0), Some(6.0), None, Some(12.0)];
+        assert_eq!(compute_indicator(Indicator::Ema, &values, 3), vec![None, None, Some(4.0), Some(4.0), Some(8.0)]);
+    }
+
+    #[test]
+    fn indicators_are_parsed_by_name() {
+        assert!(matches!(Indicator::parse("ema"), Some(Indicator::Ema)));
+        assert!(matches!(Indicator::parse("pct_change"), Some(Indicator::PctChange)));
+        assert!(Indicator::parse("macd").is_none());
+    }
+}
@@ -1,7 +1,11 @@
-use axum::{extract::Query, response::Html, Json};
+use std::str::FromStr;
+
+use axum::{extract::{Path, Query}, response::Html, Json};
+use rust_decimal::Decimal;
+use tokio_postgres::{types::ToSql, Row};
 use serde::Deserialize;
 use serde_json::json;
-use crate::{db::establish_connection, model::{EarningInterval, Pool, RunePoolInterval, SwapsInterval}}; 
+use crate::{config::DEFAULT_NETWORK, db::establish_connection, model::{Action, ALL_POOLS, Candle, Churn, EarningInterval, LiquidityChangeInterval, NetworkSnapshot, Pool, PoolEarningInterval, PoolSnapshot, PoolStats, RunePoolInterval, SaverInterval, SwapsInterval, TvlInterval}};
 use crate::model::DepthInterval; 
 #[derive(Deserialize)]
 pub struct QueryParams {
@@ -12,109 +16,152 @@ pub struct QueryParams {
     sort_by: Option<String>,      
     order: Option<String>, 
     pool: Option<String>,      
-    interval: Option<String> 
+    interval: Option<String>,
+    #[serde(rename = "type")]
+    action_type: Option<String>,
+    address: Option<String>,
+    network: Option<String>,
 }
 
-pub async fn show_homepage() -> Html<&'static str> {
-    Html("<h1>Welcome to Midgard API Fetcher</h1><p>Use the API endpoints: /depth, /swap, /earnings, /rune</p>")
+impl QueryParams {
+    /// Network the rows are read from, mainnet unless `network` says otherwise.
+    pub fn network(&self) -> &str {
+        self.network.as_deref().unwrap_or(DEFAULT_NETWORK)
+    }
 }
 
-pub async fn get_depth_history(Query(params): Query<QueryParams>) -> Json<serde_json::Value> {
-    match establish_connection().await {
-        Ok(client) => {
-            let mut query = String::new();
+/// Quotes a value as an SQL string literal.
+pub fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
 
-            // Handle interval logic
-            if let Some(interval) = &params.interval {
-                match interval.as_str() {
-                    "day" => {
-                        query.push_str("SELECT DISTINCT ON (date_trunc('day', to_timestamp(end_time::int))) * FROM depth_intervals");
-                    }
-                    "week" => {
-                        query.push_str("SELECT DISTINCT ON (date_trunc('week', to_timestamp(end_time::int))) * FROM depth_intervals");
-                    }
-                    "month" => {
-                        query.push_str("SELECT DISTINCT ON (date_trunc('month', to_timestamp(end_time::int))) * FROM depth_intervals");
-                    }
-                    "year" => {
-                        query.push_str("SELECT DISTINCT ON (date_trunc('year', to_timestamp(end_time::int))) * FROM depth_intervals");
-                    }
-                    _ => {
-                        query.push_str("SELECT * FROM depth_intervals");
-                    }
-                }
-            } else {
-                query.push_str("SELECT * FROM depth_intervals"); 
-            }
+/// When the newest of `rows` was stored, served as `lastInsertedAt` for `Last-Modified`.
+pub fn last_inserted_at(rows: &[Row]) -> Option<String> {
+    rows.iter()
+        .filter_map(|row| row.get::<_, Option<String>>("inserted_at")?.parse::<i64>().ok())
+        .max()
+        .map(|time| time.to_string())
+}
 
-            let mut filters = Vec::new();
+/// Rows before `page` of `limit` rows, pages count from 1 and page 0 is read as the first.
+fn page_offset(page: Option<u32>, limit: u32) -> i64 {
+    i64::from(page.unwrap_or(1).max(1) - 1).saturating_mul(i64::from(limit))
+}
 
-            // Handle filters
-            if let Some(start_time) = &params.start_time {
-                filters.push(format!("start_time >= '{}'", start_time)); 
-            }
-            if let Some(end_time) = &params.end_time {
-                filters.push(format!("end_time <= '{}'", end_time)); 
-            }
+/// Numeric columns of the tables `history_query_by` reads, the only ones `sort_by` may name
+/// besides their time columns.
+fn sortable_columns(table: &str) -> &'static [&'static str] {
+    match table {
+        "depth_intervals" => &[
+            "asset_depth", "asset_price", "asset_price_usd", "liquidity_units", "luvi", "members_count", "rune_depth", "synth_supply",
+            "synth_units", "units",
+        ],
+        "swap_history_intervals" => &[
+            "average_slip", "from_trade_average_slip", "from_trade_count", "from_trade_fees", "from_trade_volume", "from_trade_volume_usd",
+            "rune_price_usd", "synth_mint_average_slip", "synth_mint_count", "synth_mint_fees", "synth_mint_volume", "synth_mint_volume_usd",
+            "synth_redeem_average_slip", "synth_redeem_count", "synth_redeem_fees", "synth_redeem_volume", "synth_redeem_volume_usd",
+            "to_asset_average_slip", "to_asset_count", "to_asset_fees", "to_asset_volume", "to_asset_volume_usd", "to_rune_average_slip",
+            "to_rune_count", "to_rune_fees", "to_rune_volume", "to_rune_volume_usd", "total_count", "total_fees", "total_volume",
+            "total_volume_usd",
+        ],
+        "rune_pool_intervals" => &["count", "units"],
+        "tvl_intervals" => &["rune_price_usd", "total_value_bonded", "total_value_locked", "total_value_locked_usd", "total_value_pooled"],
+        "liquidity_change_intervals" => &[
+            "add_asset_liquidity_volume", "add_liquidity_count", "add_liquidity_volume", "add_liquidity_volume_usd",
+            "add_rune_liquidity_volume", "impermanent_loss_protection_paid", "net", "rune_price_usd", "withdraw_asset_volume",
+            "withdraw_count", "withdraw_rune_volume", "withdraw_volume", "withdraw_volume_usd",
+        ],
+        "saver_intervals" => &["savers_count", "savers_depth", "savers_units"],
+        "pool_snapshots" => &[
+            "annual_percentage_rate", "asset_depth", "asset_price", "asset_price_usd", "average_slip", "liquidity_units", "pool_apy",
+            "rune_depth", "savers_apr", "savers_depth", "savers_units", "swap_count", "swap_volume", "synth_supply", "synth_units",
+            "total_fees", "unique_member_count", "unique_swapper_count", "units", "volume24h",
+        ],
+        "network_snapshots" => &[
+            "active_node_count", "block_reward", "bond_reward", "bonding_apy", "liquidity_apy", "next_churn_height", "pool_reward",
+            "pool_share_factor", "standby_node_count", "total_active_bond", "total_pooled_rune", "total_reserve", "total_standby_bond",
+        ],
+        _ => &[],
+    }
+}
 
-            // Add filters to query
-            if !filters.is_empty() {
-                query.push_str(" WHERE ");
-                query.push_str(&filters.join(" AND "));
-            }
+/// Builds the SELECT shared by the flat interval tables: `interval` keeps one row
+/// per day/week/month/year bucket, then time filters, sorting and paging apply.
+/// `filters` are extra WHERE conditions, such as the pool of a per-pool table, next to the network's.
+fn history_query(table: &str, params: &QueryParams, filters: Vec<String>) -> String {
+    history_query_by(table, "start_time", "end_time", params, filters)
+}
 
-            let sort_by = params.sort_by.as_deref().unwrap_or("end_time"); 
-            let order = params.order.as_deref().unwrap_or("DESC");
+/// `history_query` for tables whose rows are bounded by other time columns,
+/// snapshots use the same column for both.
+fn history_query_by(table: &str, start_column: &str, end_column: &str, params: &QueryParams, mut filters: Vec<String>) -> String {
+    let bucket = match params.interval.as_deref() {
+        Some(interval @ ("day" | "week" | "month" | "year")) => Some(interval),
+        _ => None,
+    };
 
-            if let Some(interval) = &params.interval {
-                match interval.as_str() {
-                    "day" => {
-                        query.push_str(&format!(" ORDER BY date_trunc('day', to_timestamp(end_time::int)), {} {}", sort_by, order));
-                    }
-                    "week" => {
-                        query.push_str(&format!(" ORDER BY date_trunc('week', to_timestamp(end_time::int)), {} {}", sort_by, order));
-                    }
-                    "month" => {
-                        query.push_str(&format!(" ORDER BY date_trunc('month', to_timestamp(end_time::int)), {} {}", sort_by, order));
-                    }
-                    "year" => {
-                        query.push_str(&format!(" ORDER BY date_trunc('year', to_timestamp(end_time::int)), {} {}", sort_by, order));
-                    }
-                    _ => {
-                        query.push_str(&format!(" ORDER BY {} {}", sort_by, order));
-                    }
-                }
-            } else {
-                query.push_str(&format!(" ORDER BY {} {}", sort_by, order));
-            }
+    let mut query = match bucket {
+        Some(bucket) => format!("SELECT DISTINCT ON (date_trunc('{}', to_timestamp({}::int))) * FROM {}", bucket, end_column, table),
+        None => format!("SELECT * FROM {}", table),
+    };
 
-            let limit = params.limit.unwrap_or(400); 
-            let page = params.page.unwrap_or(1); 
-            let offset = (page - 1) * limit; 
-            query.push_str(&format!(" LIMIT {} OFFSET {}", limit, offset));
+    filters.push(format!("network = {}", quote(params.network())));
+
+    // Times are epoch seconds, anything else is ignored rather than spliced into the SQL
+    if let Some(start_time) = params.start_time.as_deref().and_then(|t| t.parse::<i64>().ok()) {
+        filters.push(format!("{} >= '{}'", start_column, start_time));
+    }
+    if let Some(end_time) = params.end_time.as_deref().and_then(|t| t.parse::<i64>().ok()) {
+        filters.push(format!("{} <= '{}'", end_column, end_time));
+    }
+
+    if !filters.is_empty() {
+        query.push_str(" WHERE ");
+        query.push_str(&filters.join(" AND "));
+    }
+
+    // Any other column would fail the query, so it sorts by time instead
+    let sort_by = params
+        .sort_by
+        .as_deref()
+        .filter(|column| *column == start_column || sortable_columns(table).contains(column))
+        .unwrap_or(end_column);
+    let order = match params.order.as_deref() {
+        Some(order) if order.eq_ignore_ascii_case("asc") => "ASC",
+        _ => "DESC",
+    };
+
+    match bucket {
+        Some(bucket) => query.push_str(&format!(
+            " ORDER BY date_trunc('{}', to_timestamp({}::int)), {}::numeric {} NULLS LAST",
+            bucket, end_column, sort_by, order
+        )),
+        None => query.push_str(&format!(" ORDER BY {}::numeric {} NULLS LAST", sort_by, order)),
+    }
+
+    let limit = params.limit.unwrap_or(400);
+    query.push_str(&format!(" LIMIT {} OFFSET {}", limit, page_offset(params.page, limit)));
+
+    query
+}
+
+pub async fn show_homepage() -> Html<&'static str> {
+    Html("<h1>Welcome to Midgard API Fetcher</h1><p>Use the API endpoints, of mainnet unless <code>?network=</code> names another: /depth, /swap, /earnings, /rune, /tvl, /liquidity, /savers, /pools, /pools/{asset}/snapshots, /actions, /network, /earnings/pools/{pool}, /analytics/apy, /analytics/lp-performance, /candles, /analytics/indicators, /alerts, /alerts/events, /ws, /events, /graphql, /usage</p>")
+}
+
+pub async fn get_depth_history(Query(params): Query<QueryParams>) -> Json<serde_json::Value> {
+    match establish_connection().await {
+        Ok(client) => {
+            let pool = params.pool.as_deref().unwrap_or("BTC.BTC");
+            let query = history_query("depth_intervals", &params, vec![format!("pool = {}", quote(pool))]);
 
             println!("Generated SQL Query: {}", query);
 
             let rows = client.query(&query, &[]).await.unwrap();
 
-            let intervals: Vec<DepthInterval> = rows.iter().map(|row| {
-                DepthInterval {
-                    asset_depth: row.get("asset_depth"),
-                    asset_price: row.get("asset_price"),
-                    asset_price_usd: row.get("asset_price_usd"),
-                    end_time: row.get("end_time"),
-                    liquidity_units: row.get("liquidity_units"),
-                    luvi: row.get("luvi"),
-                    members_count: row.get("members_count"),
-                    rune_depth: row.get("rune_depth"),
-                    start_time: row.get("start_time"),
-                    synth_supply: row.get("synth_supply"),
-                    synth_units: row.get("synth_units"),
-                    units: row.get("units"),
-                }
-            }).collect();
+            let intervals: Vec<DepthInterval> = rows.iter().map(depth_interval_from_row).collect();
 
-            Json(json!({ "data": intervals }))
+            Json(json!({ "data": intervals, "lastInsertedAt": last_inserted_at(&rows) }))
         }
         Err(e) => {
             eprintln!("Failed to connect to the database: {}", e);
@@ -128,129 +175,16 @@ pub async fn get_depth_history(Query(params): Query<QueryParams>) -> Json<serde_
 pub async fn get_swaps_history(Query(params): Query<QueryParams>) -> Json<serde_json::Value> {
     match establish_connection().await {
         Ok(client) => {
-            let mut query = String::new();
-
-            if let Some(interval) = &params.interval {
-                match interval.as_str() {
-                    "day" => {
-                        query.push_str("SELECT DISTINCT ON (date_trunc('day', to_timestamp(end_time::int))) * FROM swap_history_intervals");
-                    }
-                    "week" => {
-                        query.push_str("SELECT DISTINCT ON (date_trunc('week', to_timestamp(end_time::int))) * FROM swap_history_intervals");
-                    }
-                    "month" => {
-                        query.push_str("SELECT DISTINCT ON (date_trunc('month', to_timestamp(end_time::int))) * FROM swap_history_intervals");
-                    }
-                    "year" => {
-                        query.push_str("SELECT DISTINCT ON (date_trunc('year', to_timestamp(end_time::int))) * FROM swap_history_intervals");
-                    }
-                    _ => {
-                        query.push_str("SELECT * FROM swap_history_intervals"); 
-                    }
-                }
-            } else {
-                query.push_str("SELECT * FROM swap_history_intervals"); 
-            }
-
-            let mut filters = Vec::new();
-
-            if let Some(start_time) = &params.start_time {
-                filters.push(format!("start_time >= '{}'", start_time)); 
-            }
-            if let Some(end_time) = &params.end_time {
-                filters.push(format!("end_time <= '{}'", end_time)); 
-            }
-
-            if !filters.is_empty() {
-                query.push_str(" WHERE ");
-                query.push_str(&filters.join(" AND "));
-            }
-
-            let sort_column = params.sort_by.as_deref().unwrap_or("end_time"); 
-            let order = params.order.as_deref().unwrap_or("DESC");
-
-            if let Some(interval) = &params.interval {
-                match interval.as_str() {
-                    "day" => {
-                        query.push_str(&format!(
-                            " ORDER BY date_trunc('day', to_timestamp(end_time::int)), {} {}",
-                            sort_column, order
-                        ));
-                    }
-                    "week" => {
-                        query.push_str(&format!(
-                            " ORDER BY date_trunc('week', to_timestamp(end_time::int)), {} {}",
-                            sort_column, order
-                        ));
-                    }
-                    "month" => {
-                        query.push_str(&format!(
-                            " ORDER BY date_trunc('month', to_timestamp(end_time::int)), {} {}",
-                            sort_column, order
-                        ));
-                    }
-                    "year" => {
-                        query.push_str(&format!(
-                            " ORDER BY date_trunc('year', to_timestamp(end_time::int)), {} {}",
-                            sort_column, order
-                        ));
-                    }
-                    _ => {
-                        query.push_str(&format!(" ORDER BY {} {}", sort_column, order));
-                    }
-                }
-            } else {
-                query.push_str(&format!(" ORDER BY {} {}", sort_column, order));
-            }
-
-            let limit = params.limit.unwrap_or(400);
-            let page = params.page.unwrap_or(1);
-            let offset = (page - 1) * limit;
-            query.push_str(&format!(" LIMIT {} OFFSET {}", limit, offset));
+            let pool = params.pool.as_deref().unwrap_or(ALL_POOLS);
+            let query = history_query("swap_history_intervals", &params, vec![format!("pool = {}", quote(pool))]);
 
             println!("Generated SQL Query: {}", query);
 
             let rows = client.query(&query, &[]).await.unwrap();
 
-            let intervals: Vec<SwapsInterval> = rows.iter().map(|row| {
-                SwapsInterval {
-                    average_slip: row.get("average_slip"),
-                    end_time: row.get("end_time"),
-                    from_trade_average_slip: row.get("from_trade_average_slip"),
-                    from_trade_count: row.get("from_trade_count"),
-                    from_trade_fees: row.get("from_trade_fees"),
-                    from_trade_volume: row.get("from_trade_volume"),
-                    from_trade_volume_usd: row.get("from_trade_volume_usd"),
-                    rune_price_usd: row.get("rune_price_usd"),
-                    start_time: row.get("start_time"),
-                    synth_mint_average_slip: row.get("synth_mint_average_slip"),
-                    synth_mint_count: row.get("synth_mint_count"),
-                    synth_mint_fees: row.get("synth_mint_fees"),
-                    synth_mint_volume: row.get("synth_mint_volume"),
-                    synth_mint_volume_usd: row.get("synth_mint_volume_usd"),
-                    synth_redeem_average_slip: row.get("synth_redeem_average_slip"),
-                    synth_redeem_count: row.get("synth_redeem_count"),
-                    synth_redeem_fees: row.get("synth_redeem_fees"),
-                    synth_redeem_volume: row.get("synth_redeem_volume"),
-                    synth_redeem_volume_usd: row.get("synth_redeem_volume_usd"),
-                    to_asset_average_slip: row.get("to_asset_average_slip"),
-                    to_asset_count: row.get("to_asset_count"),
-                    to_asset_fees: row.get("to_asset_fees"),
-                    to_asset_volume: row.get("to_asset_volume"),
-                    to_asset_volume_usd: row.get("to_asset_volume_usd"),
-                    to_rune_average_slip: row.get("to_rune_average_slip"),
-                    to_rune_count: row.get("to_rune_count"),
-                    to_rune_fees: row.get("to_rune_fees"),
-                    to_rune_volume: row.get("to_rune_volume"),
-                    to_rune_volume_usd: row.get("to_rune_volume_usd"),
-                    total_count: row.get("total_count"),
-                    total_fees: row.get("total_fees"),
-                    total_volume: row.get("total_volume"),
-                    total_volume_usd: row.get("total_volume_usd"),
-                }
-            }).collect();
+            let intervals: Vec<SwapsInterval> = rows.iter().map(swaps_interval_from_row).collect();
 
-            Json(json!({ "data": intervals }))
+            Json(json!({ "data": intervals, "lastInsertedAt": last_inserted_at(&rows) }))
         }
         Err(e) => {
             eprintln!("Failed to connect to the database: {}", e);
@@ -265,100 +199,15 @@ pub async fn get_swaps_history(Query(params): Query<QueryParams>) -> Json<serde_
 pub async fn get_rune_pool_history(Query(params): Query<QueryParams>) -> Json<serde_json::Value> {
     match establish_connection().await {
         Ok(client) => {
-            let mut query = String::new();
-
-            if let Some(interval) = &params.interval {
-                match interval.as_str() {
-                    "day" => {
-                        query.push_str("SELECT DISTINCT ON (date_trunc('day', to_timestamp(end_time::int))) * FROM rune_pool_intervals");
-                    }
-                    "week" => {
-                        query.push_str("SELECT DISTINCT ON (date_trunc('week', to_timestamp(end_time::int))) * FROM rune_pool_intervals");
-                    }
-                    "month" => {
-                        query.push_str("SELECT DISTINCT ON (date_trunc('month', to_timestamp(end_time::int))) * FROM rune_pool_intervals");
-                    }
-                    "year" => {
-                        query.push_str("SELECT DISTINCT ON (date_trunc('year', to_timestamp(end_time::int))) * FROM rune_pool_intervals");
-                    }
-                    _ => {
-                        query.push_str("SELECT * FROM rune_pool_intervals"); 
-                    }
-                }
-            } else {
-                query.push_str("SELECT * FROM rune_pool_intervals"); 
-            }
-
-            let mut filters = Vec::new();
-
-            if let Some(start_time) = &params.start_time {
-                filters.push(format!("start_time >= '{}'", start_time)); 
-            }
-            if let Some(end_time) = &params.end_time {
-                filters.push(format!("end_time <= '{}'", end_time));
-            }
-
-            if !filters.is_empty() {
-                query.push_str(" WHERE ");
-                query.push_str(&filters.join(" AND "));
-            }
-
-            let sort_column = params.sort_by.as_deref().unwrap_or("end_time"); 
-            let order = params.order.as_deref().unwrap_or("DESC"); 
-
-            if let Some(interval) = &params.interval {
-                match interval.as_str() {
-                    "day" => {
-                        query.push_str(&format!(
-                            " ORDER BY date_trunc('day', to_timestamp(end_time::int)), {} {}",
-                            sort_column, order
-                        ));
-                    }
-                    "week" => {
-                        query.push_str(&format!(
-                            " ORDER BY date_trunc('week', to_timestamp(end_time::int)), {} {}",
-                            sort_column, order
-                        ));
-                    }
-                    "month" => {
-                        query.push_str(&format!(
-                            " ORDER BY date_trunc('month', to_timestamp(end_time::int)), {} {}",
-                            sort_column, order
-                        ));
-                    }
-                    "year" => {
-                        query.push_str(&format!(
-                            " ORDER BY date_trunc('year', to_timestamp(end_time::int)), {} {}",
-                            sort_column, order
-                        ));
-                    }
-                    _ => {
-                        query.push_str(&format!(" ORDER BY {} {}", sort_column, order));
-                    }
-                }
-            } else {
-                query.push_str(&format!(" ORDER BY {} {}", sort_column, order));
-            }
-
-            let limit = params.limit.unwrap_or(400); 
-            let page = params.page.unwrap_or(1); 
-            let offset = (page - 1) * limit; 
-            query.push_str(&format!(" LIMIT {} OFFSET {}", limit, offset));
+            let query = history_query("rune_pool_intervals", &params, Vec::new());
 
             println!("Generated SQL Query: {}", query);
 
             let rows = client.query(&query, &[]).await.unwrap();
 
-            let intervals: Vec<RunePoolInterval> = rows.iter().map(|row| {
-                RunePoolInterval {
-                    count: row.get("count"),
-                    end_time: row.get("end_time"),
-                    start_time: row.get("start_time"),
-                    units: row.get("units"),
-                }
-            }).collect();
+            let intervals: Vec<RunePoolInterval> = rows.iter().map(rune_pool_interval_from_row).collect();
 
-            Json(json!({ "data": intervals }))
+            Json(json!({ "data": intervals, "lastInsertedAt": last_inserted_at(&rows) }))
         }
         Err(e) => {
             eprintln!("Failed to connect to the database: {}", e);
@@ -369,17 +218,83 @@ pub async fn get_rune_pool_history(Query(params): Query<QueryParams>) -> Json<se
 
 
 
+pub fn depth_interval_from_row(row: &Row) -> DepthInterval {
+    DepthInterval {
+        asset_depth: row.get("asset_depth"),
+        asset_price: row.get("asset_price"),
+        asset_price_usd: row.get("asset_price_usd"),
+        end_time: row.get("end_time"),
+        liquidity_units: row.get("liquidity_units"),
+        luvi: row.get("luvi"),
+        members_count: row.get("members_count"),
+        pool: row.get("pool"),
+        rune_depth: row.get("rune_depth"),
+        start_time: row.get("start_time"),
+        synth_supply: row.get("synth_supply"),
+        synth_units: row.get("synth_units"),
+        units: row.get("units"),
+    }
+}
+
+pub fn swaps_interval_from_row(row: &Row) -> SwapsInterval {
+    SwapsInterval {
+        average_slip: row.get("average_slip"),
+        end_time: row.get("end_time"),
+        from_trade_average_slip: row.get("from_trade_average_slip"),
+        from_trade_count: row.get("from_trade_count"),
+        from_trade_fees: row.get("from_trade_fees"),
+        from_trade_volume: row.get("from_trade_volume"),
+        from_trade_volume_usd: row.get("from_trade_volume_usd"),
+        pool: row.get("pool"),
+        rune_price_usd: row.get("rune_price_usd"),
+        start_time: row.get("start_time"),
+        synth_mint_average_slip: row.get("synth_mint_average_slip"),
+        synth_mint_count: row.get("synth_mint_count"),
+        synth_mint_fees: row.get("synth_mint_fees"),
+        synth_mint_volume: row.get("synth_mint_volume"),
+        synth_mint_volume_usd: row.get("synth_mint_volume_usd"),
+        synth_redeem_average_slip: row.get("synth_redeem_average_slip"),
+        synth_redeem_count: row.get("synth_redeem_count"),
+        synth_redeem_fees: row.get("synth_redeem_fees"),
+        synth_redeem_volume: row.get("synth_redeem_volume"),
+        synth_redeem_volume_usd: row.get("synth_redeem_volume_usd"),
+        to_asset_average_slip: row.get("to_asset_average_slip"),
+        to_asset_count: row.get("to_asset_count"),
+        to_asset_fees: row.get("to_asset_fees"),
+        to_asset_volume: row.get("to_asset_volume"),
+        to_asset_volume_usd: row.get("to_asset_volume_usd"),
+        to_rune_average_slip: row.get("to_rune_average_slip"),
+        to_rune_count: row.get("to_rune_count"),
+        to_rune_fees: row.get("to_rune_fees"),
+        to_rune_volume: row.get("to_rune_volume"),
+        to_rune_volume_usd: row.get("to_rune_volume_usd"),
+        total_count: row.get("total_count"),
+        total_fees: row.get("total_fees"),
+        total_volume: row.get("total_volume"),
+        total_volume_usd: row.get("total_volume_usd"),
+    }
+}
+
+pub fn rune_pool_interval_from_row(row: &Row) -> RunePoolInterval {
+    RunePoolInterval {
+        count: row.get("count"),
+        end_time: row.get("end_time"),
+        start_time: row.get("start_time"),
+        units: row.get("units"),
+    }
+}
+
 pub async fn get_earning_history(Query(params): Query<QueryParams>) -> Json<serde_json::Value> {
     match establish_connection().await {
         Ok(client) => {
             let mut query = String::from("SELECT ei.avg_node_count, ei.block_rewards, ei.bonding_earnings, ei.earnings, ei.end_time, \
-                                          ei.liquidity_earnings, ei.liquidity_fees, ei.rune_price_usd, ei.start_time, p.pool, \
+                                          ei.inserted_at, ei.liquidity_earnings, ei.liquidity_fees, ei.rune_price_usd, ei.start_time, p.pool, \
                                           p.asset_liquidity_fees, p.earnings, p.rewards, p.rune_liquidity_fees, p.saver_earning, \
                                           p.total_liquidity_fees_rune \
                                           FROM earning_intervals ei \
                                           LEFT JOIN pools p ON ei.id = p.interval_id");
 
-            let mut filters = Vec::new();
+            let mut filters = vec![format!("ei.network = {}", quote(params.network()))];
 
             if let Some(interval) = &params.interval {
                 match interval.as_str() {
@@ -401,15 +316,16 @@ pub async fn get_earning_history(Query(params): Query<QueryParams>) -> Json<serd
                 }
             }
 
-            if let Some(start_time) = &params.start_time {
+            // Times are epoch seconds, anything else is ignored rather than spliced into the SQL
+            if let Some(start_time) = params.start_time.as_deref().and_then(|t| t.parse::<i64>().ok()) {
                 filters.push(format!("ei.start_time >= '{}'", start_time));
             }
-            if let Some(end_time) = &params.end_time {
+            if let Some(end_time) = params.end_time.as_deref().and_then(|t| t.parse::<i64>().ok()) {
                 filters.push(format!("ei.end_time <= '{}'", end_time));
             }
 
             if let Some(pool) = &params.pool {
-                filters.push(format!("p.pool = '{}'", pool));
+                filters.push(format!("p.pool = {}", quote(pool)));
             }
 
             if !filters.is_empty() {
@@ -418,8 +334,11 @@ pub async fn get_earning_history(Query(params): Query<QueryParams>) -> Json<serd
             }
 
             if let Some(sort_by) = &params.sort_by {
-                let order = params.order.as_deref().unwrap_or("DESC"); 
-            
+                let order = match params.order.as_deref() {
+                    Some(order) if order.eq_ignore_ascii_case("asc") => "ASC",
+                    _ => "DESC",
+                };
+
                 let valid_sort_by = match sort_by.as_str() {
                     "start_time" => "ei.start_time",
                     "end_time" => "ei.end_time",
@@ -434,9 +353,7 @@ pub async fn get_earning_history(Query(params): Query<QueryParams>) -> Json<serd
             }
 
             let limit = params.limit.unwrap_or(100);
-            let page = params.page.unwrap_or(1);
-            let offset = (page - 1) * limit;
-            query.push_str(&format!(" LIMIT {} OFFSET {}", limit, offset));
+            query.push_str(&format!(" LIMIT {} OFFSET {}", limit, page_offset(params.page, limit)));
             println!("{:?}",query);
 
             let rows = client.query(&query, &[]).await.unwrap();
@@ -472,7 +389,529 @@ pub async fn get_earning_history(Query(params): Query<QueryParams>) -> Json<serd
                 }
             }
 
-            Json(json!({ "data": earnings }))
+            Json(json!({ "data": earnings, "lastInsertedAt": last_inserted_at(&rows) }))
+        }
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {}", e);
+            Json(json!({ "error": "Failed to connect to database" }))
+        }
+    }
+}
+
+
+pub async fn get_tvl_history(Query(params): Query<QueryParams>) -> Json<serde_json::Value> {
+    match establish_connection().await {
+        Ok(client) => {
+            let query = history_query("tvl_intervals", &params, Vec::new());
+
+            println!("Generated SQL Query: {}", query);
+
+            let rows = client.query(&query, &[]).await.unwrap();
+
+            let intervals: Vec<TvlInterval> = rows.iter().map(|row| {
+                TvlInterval {
+                    end_time: row.get("end_time"),
+                    pools_depth: serde_json::from_str(row.get("pools_depth")).unwrap_or_default(),
+                    rune_price_usd: row.get("rune_price_usd"),
+                    start_time: row.get("start_time"),
+                    total_value_bonded: row.get("total_value_bonded"),
+                    total_value_locked: row.get("total_value_locked"),
+                    total_value_locked_usd: row.get("total_value_locked_usd"),
+                    total_value_pooled: row.get("total_value_pooled"),
+                }
+            }).collect();
+
+            Json(json!({ "data": intervals, "lastInsertedAt": last_inserted_at(&rows) }))
+        }
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {}", e);
+            Json(json!({ "error": "Failed to connect to database" }))
+        }
+    }
+}
+
+
+pub async fn get_liquidity_history(Query(params): Query<QueryParams>) -> Json<serde_json::Value> {
+    match establish_connection().await {
+        Ok(client) => {
+            let pool = params.pool.as_deref().unwrap_or("BTC.BTC");
+            let query = history_query("liquidity_change_intervals", &params, vec![format!("pool = {}", quote(pool))]);
+
+            println!("Generated SQL Query: {}", query);
+
+            let rows = client.query(&query, &[]).await.unwrap();
+
+            let intervals: Vec<LiquidityChangeInterval> = rows.iter().map(|row| {
+                LiquidityChangeInterval {
+                    add_asset_liquidity_volume: row.get("add_asset_liquidity_volume"),
+                    add_liquidity_count: row.get("add_liquidity_count"),
+                    add_liquidity_volume: row.get("add_liquidity_volume"),
+                    add_liquidity_volume_usd: row.get("add_liquidity_volume_usd"),
+                    add_rune_liquidity_volume: row.get("add_rune_liquidity_volume"),
+                    end_time: row.get("end_time"),
+                    impermanent_loss_protection_paid: row.get("impermanent_loss_protection_paid"),
+                    net: row.get("net"),
+                    pool: row.get("pool"),
+                    rune_price_usd: row.get("rune_price_usd"),
+                    start_time: row.get("start_time"),
+                    withdraw_asset_volume: row.get("withdraw_asset_volume"),
+                    withdraw_count: row.get("withdraw_count"),
+                    withdraw_rune_volume: row.get("withdraw_rune_volume"),
+                    withdraw_volume: row.get("withdraw_volume"),
+                    withdraw_volume_usd: row.get("withdraw_volume_usd"),
+                }
+            }).collect();
+
+            Json(json!({ "data": intervals, "lastInsertedAt": last_inserted_at(&rows) }))
+        }
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {}", e);
+            Json(json!({ "error": "Failed to connect to database" }))
+        }
+    }
+}
+
+
+pub async fn get_savers_history(Query(params): Query<QueryParams>) -> Json<serde_json::Value> {
+    match establish_connection().await {
+        Ok(client) => {
+            let pool = params.pool.as_deref().unwrap_or("BTC.BTC");
+            let query = history_query("saver_intervals", &params, vec![format!("pool = {}", quote(pool))]);
+
+            println!("Generated SQL Query: {}", query);
+
+            let rows = client.query(&query, &[]).await.unwrap();
+
+            let intervals: Vec<SaverInterval> = rows.iter().map(|row| {
+                SaverInterval {
+                    end_time: row.get("end_time"),
+                    pool: row.get("pool"),
+                    savers_count: row.get("savers_count"),
+                    savers_depth: row.get("savers_depth"),
+                    savers_units: row.get("savers_units"),
+                    start_time: row.get("start_time"),
+                }
+            }).collect();
+
+            Json(json!({ "data": intervals, "lastInsertedAt": last_inserted_at(&rows) }))
+        }
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {}", e);
+            Json(json!({ "error": "Failed to connect to database" }))
+        }
+    }
+}
+
+
+fn pool_snapshot_from_row(row: &Row) -> PoolSnapshot {
+    PoolSnapshot {
+        annual_percentage_rate: row.get("annual_percentage_rate"),
+        asset: row.get("asset"),
+        asset_depth: row.get("asset_depth"),
+        asset_price: row.get("asset_price"),
+        asset_price_usd: row.get("asset_price_usd"),
+        liquidity_units: row.get("liquidity_units"),
+        pool_apy: row.get("pool_apy"),
+        rune_depth: row.get("rune_depth"),
+        savers_apr: row.get("savers_apr"),
+        savers_depth: row.get("savers_depth"),
+        savers_units: row.get("savers_units"),
+        snapshot_time: row.get("snapshot_time"),
+        status: row.get("status"),
+        synth_supply: row.get("synth_supply"),
+        synth_units: row.get("synth_units"),
+        units: row.get("units"),
+        volume24h: row.get("volume24h"),
+        stats: PoolStats {
+            average_slip: row.get("average_slip"),
+            swap_count: row.get("swap_count"),
+            swap_volume: row.get("swap_volume"),
+            total_fees: row.get("total_fees"),
+            unique_member_count: row.get("unique_member_count"),
+            unique_swapper_count: row.get("unique_swapper_count"),
+        },
+    }
+}
+
+/// Latest snapshot of every pool.
+pub async fn get_latest_pools(Query(params): Query<QueryParams>) -> Json<serde_json::Value> {
+    match establish_connection().await {
+        Ok(client) => {
+            let query = "SELECT DISTINCT ON (asset) * FROM pool_snapshots WHERE network = $1 ORDER BY asset, snapshot_time::bigint DESC";
+
+            let rows = client.query(query, &[&params.network()]).await.unwrap();
+
+            let pools: Vec<PoolSnapshot> = rows.iter().map(pool_snapshot_from_row).collect();
+
+            Json(json!({ "data": pools }))
+        }
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {}", e);
+            Json(json!({ "error": "Failed to connect to database" }))
+        }
+    }
+}
+
+/// Snapshot history of one pool, `start_time`/`end_time` bound the snapshot time.
+pub async fn get_pool_snapshots(Path(asset): Path<String>, Query(params): Query<QueryParams>) -> Json<serde_json::Value> {
+    match establish_connection().await {
+        Ok(client) => {
+            let query = history_query_by("pool_snapshots", "snapshot_time", "snapshot_time", &params, vec![format!("asset = {}", quote(&asset))]);
+
+            println!("Generated SQL Query: {}", query);
+
+            let rows = client.query(&query, &[]).await.unwrap();
+
+            let snapshots: Vec<PoolSnapshot> = rows.iter().map(pool_snapshot_from_row).collect();
+
+            Json(json!({ "data": snapshots }))
+        }
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {}", e);
+            Json(json!({ "error": "Failed to connect to database" }))
+        }
+    }
+}
+
+
+fn action_from_row(row: &Row) -> Action {
+    Action {
+        action_type: row.get("action_type"),
+        addresses: row.get("addresses"),
+        affiliate_fee: row.get("affiliate_fee"),
+        date: row.get("date"),
+        height: row.get("height"),
+        in_coins: serde_json::from_str(row.get("in_coins")).unwrap_or_default(),
+        is_streaming_swap: row.get("is_streaming_swap"),
+        liquidity_fee: row.get("liquidity_fee"),
+        liquidity_units: row.get("liquidity_units"),
+        network_fees: serde_json::from_str(row.get("network_fees")).unwrap_or_default(),
+        out_coins: serde_json::from_str(row.get("out_coins")).unwrap_or_default(),
+        pools: row.get("pools"),
+        status: row.get("status"),
+        streaming_swap_meta: row.get::<_, Option<&str>>("streaming_swap_meta").and_then(|meta| serde_json::from_str(meta).ok()),
+        swap_slip: row.get("swap_slip"),
+        tx_id: row.get("tx_id"),
+    }
+}
+
+/// Individual actions, filtered by `type`, `pool`, `address` and `start_time`/`end_time` in seconds.
+pub async fn get_actions(Query(params): Query<QueryParams>) -> Json<serde_json::Value> {
+    match establish_connection().await {
+        Ok(client) => {
+            let mut values: Vec<String> = vec![params.network().to_string()];
+            let mut filters = vec!["network = $1".to_string()];
+
+            if let Some(action_type) = &params.action_type {
+                values.push(action_type.clone());
+                filters.push(format!("action_type = ${}", values.len()));
+            }
+            if let Some(pool) = &params.pool {
+                values.push(pool.clone());
+                filters.push(format!("${} = ANY(pools)", values.len()));
+            }
+            if let Some(address) = &params.address {
+                values.push(address.clone());
+                filters.push(format!("${} = ANY(addresses)", values.len()));
+            }
+            if let Some(start_time) = params.start_time.as_deref().and_then(|t| t.parse::<i64>().ok()) {
+                values.push(start_time.to_string());
+                filters.push(format!("date::numeric >= ${}::text::numeric * 1000000000", values.len()));
+            }
+            if let Some(end_time) = params.end_time.as_deref().and_then(|t| t.parse::<i64>().ok()) {
+                values.push(end_time.to_string());
+                filters.push(format!("date::numeric <= ${}::text::numeric * 1000000000", values.len()));
+            }
+
+            let mut query = String::from("SELECT * FROM actions WHERE ");
+            query.push_str(&filters.join(" AND "));
+
+            let order = match params.order.as_deref() {
+                Some(order) if order.eq_ignore_ascii_case("asc") => "ASC",
+                _ => "DESC",
+            };
+            let limit = params.limit.unwrap_or(50);
+            query.push_str(&format!(" ORDER BY date::numeric {} LIMIT {} OFFSET {}", order, limit, page_offset(params.page, limit)));
+
+            println!("Generated SQL Query: {}", query);
+
+            let values: Vec<&(dyn ToSql + Sync)> = values.iter().map(|value| value as &(dyn ToSql + Sync)).collect();
+            let rows = client.query(&query, &values).await.unwrap();
+
+            let actions: Vec<Action> = rows.iter().map(action_from_row).collect();
+
+            Json(json!({ "data": actions }))
+        }
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {}", e);
+            Json(json!({ "error": "Failed to connect to database" }))
+        }
+    }
+}
+
+
+/// Network snapshot history, with the churns that happened in the same time range.
+pub async fn get_network_history(Query(params): Query<QueryParams>) -> Json<serde_json::Value> {
+    match establish_connection().await {
+        Ok(client) => {
+            let query = history_query_by("network_snapshots", "snapshot_time", "snapshot_time", &params, Vec::new());
+
+            println!("Generated SQL Query: {}", query);
+
+            let rows = client.query(&query, &[]).await.unwrap();
+
+            let snapshots: Vec<NetworkSnapshot> = rows.iter().map(|row| {
+                NetworkSnapshot {
+                    active_node_count: row.get("active_node_count"),
+                    block_reward: row.get("block_reward"),
+                    bond_reward: row.get("bond_reward"),
+                    bonding_apy: row.get("bonding_apy"),
+                    liquidity_apy: row.get("liquidity_apy"),
+                    next_churn_height: row.get("next_churn_height"),
+                    pool_reward: row.get("pool_reward"),
+                    pool_share_factor: row.get("pool_share_factor"),
+                    snapshot_time: row.get("snapshot_time"),
+                    standby_node_count: row.get("standby_node_count"),
+                    total_active_bond: row.get("total_active_bond"),
+                    total_pooled_rune: row.get("total_pooled_rune"),
+                    total_reserve: row.get("total_reserve"),
+                    total_standby_bond: row.get("total_standby_bond"),
+                }
+            }).collect();
+
+            // Churn dates are in nanoseconds
+            let start_time = params.start_time.as_deref().and_then(|t| t.parse::<i64>().ok()).unwrap_or(0);
+            let end_time = params.end_time.as_deref().and_then(|t| t.parse::<i64>().ok()).unwrap_or(i64::MAX / 1_000_000_000);
+            let rows = client
+                .query(
+                    "SELECT date, height FROM churns WHERE network = $3 AND date::numeric BETWEEN $1::bigint::numeric * 1000000000 AND $2::bigint::numeric * 1000000000 ORDER BY date::numeric",
+                    &[&start_time, &end_time, &params.network()],
+                )
+                .await
+                .unwrap();
+
+            let churns: Vec<Churn> = rows.iter().map(|row| {
+                Churn {
+                    date: row.get("date"),
+                    height: row.get("height"),
+                }
+            }).collect();
+
+            Json(json!({ "data": snapshots, "churns": churns }))
+        }
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {}", e);
+            Json(json!({ "error": "Failed to connect to database" }))
+        }
+    }
+}
+
+
+/// Numeric columns of `pools` that the per-pool earnings series can be summed and sorted by.
+const POOL_EARNING_FIELDS: [&str; 6] = [
+    "asset_liquidity_fees",
+    "earnings",
+    "rewards",
+    "rune_liquidity_fees",
+    "saver_earning",
+    "total_liquidity_fees_rune",
+];
+
+/// The pool's depth interval ending with an earnings interval, for the asset's USD price.
+const DEPTH_OF_EARNING_INTERVAL: &str =
+    "LEFT JOIN depth_intervals d ON d.network = ei.network AND d.pool = p.pool AND d.end_time = ei.end_time";
+
+/// Flat earnings time series of one pool. With `interval`, the hourly rows of each
+/// day/week/month/year are summed into one.
+pub async fn get_pool_earning_history(Path(pool): Path<String>, Query(params): Query<QueryParams>) -> Json<serde_json::Value> {
+    match establish_connection().await {
+        Ok(client) => {
+            let bucket = match params.interval.as_deref() {
+                Some(interval @ ("day" | "week" | "month" | "year")) => Some(interval),
+                _ => None,
+            };
+
+            let mut filters = vec!["p.pool = $1".to_string(), format!("ei.network = {}", quote(params.network()))];
+            if let Some(start_time) = params.start_time.as_deref().and_then(|t| t.parse::<i64>().ok()) {
+                filters.push(format!("ei.start_time::bigint >= {}", start_time));
+            }
+            if let Some(end_time) = params.end_time.as_deref().and_then(|t| t.parse::<i64>().ok()) {
+                filters.push(format!("ei.end_time::bigint <= {}", end_time));
+            }
+
+            let series = match bucket {
+                Some(bucket) => {
+                    let sums: Vec<String> = POOL_EARNING_FIELDS
+                        .iter()
+                        .map(|field| format!("SUM(p.{0}::numeric)::text AS {0}", field))
+                        .collect();
+                    // A bucket is priced at its last hour
+                    format!(
+                        "SELECT MIN(ei.start_time::bigint)::text AS start_time, MAX(ei.end_time::bigint)::text AS end_time, p.pool, {}, \
+                         (array_agg(ei.rune_price_usd ORDER BY ei.end_time::bigint DESC))[1] AS rune_price_usd, \
+                         (array_agg(d.asset_price_usd ORDER BY ei.end_time::bigint DESC))[1] AS asset_price_usd, \
+                         MAX(ei.inserted_at::bigint)::text AS inserted_at \
+                         FROM pools p JOIN earning_intervals ei ON ei.id = p.interval_id {} \
+                         WHERE {} GROUP BY date_trunc('{}', to_timestamp(ei.end_time::int)), p.pool",
+                        sums.join(", "), DEPTH_OF_EARNING_INTERVAL, filters.join(" AND "), bucket
+                    )
+                }
+                None => {
+                    let fields: Vec<String> = POOL_EARNING_FIELDS.iter().map(|field| format!("p.{}", field)).collect();
+                    format!(
+                        "SELECT ei.start_time, ei.end_time, p.pool, {}, ei.rune_price_usd, d.asset_price_usd, ei.inserted_at \
+                         FROM pools p JOIN earning_intervals ei ON ei.id = p.interval_id {} \
+                         WHERE {}",
+                        fields.join(", "), DEPTH_OF_EARNING_INTERVAL, filters.join(" AND ")
+                    )
+                }
+            };
+
+            let sort_by = params
+                .sort_by
+                .as_deref()
+                .filter(|field| *field == "start_time" || POOL_EARNING_FIELDS.contains(field))
+                .unwrap_or("end_time");
+            let order = match params.order.as_deref() {
+                Some(order) if order.eq_ignore_ascii_case("asc") => "ASC",
+                _ => "DESC",
+            };
+            let limit = params.limit.unwrap_or(400);
+            let offset = page_offset(params.page, limit);
+
+            let query = format!(
+                "SELECT * FROM ({}) series ORDER BY {}::numeric {} NULLS LAST LIMIT {} OFFSET {}",
+                series, sort_by, order, limit, offset
+            );
+
+            println!("Generated SQL Query: {}", query);
+
+            let rows = client.query(&query, &[&pool]).await.unwrap();
+
+            let intervals: Vec<PoolEarningInterval> = rows.iter().map(|row| {
+                PoolEarningInterval {
+                    asset_liquidity_fees: row.get("asset_liquidity_fees"),
+                    asset_price_usd: row.get("asset_price_usd"),
+                    earnings: row.get("earnings"),
+                    end_time: row.get("end_time"),
+                    pool: row.get("pool"),
+                    rewards: row.get("rewards"),
+                    rune_liquidity_fees: row.get("rune_liquidity_fees"),
+                    rune_price_usd: row.get("rune_price_usd"),
+                    saver_earning: row.get("saver_earning"),
+                    start_time: row.get("start_time"),
+                    total_liquidity_fees_rune: row.get("total_liquidity_fees_rune"),
+                }
+            }).collect();
+
+            Json(json!({ "data": intervals, "lastInsertedAt": last_inserted_at(&rows) }))
+        }
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {}", e);
+            Json(json!({ "error": "Failed to connect to database" }))
+        }
+    }
+}
+
+
+/// OHLC candles of a pool's asset price. Depth rows only hold the price at the end of
+/// each hour, so each hour opens at the previous hour's close.
+///
+/// `volume` is depth-weighted: the hourly swap volumes averaged with each hour weighted by
+/// the pool's RUNE depth then, so hours traded against a deep pool count for more than
+/// those traded against a shallow one. `totalVolume` is the plain sum. For `1h` candles
+/// both are the hour's volume.
+pub async fn get_candles(Query(params): Query<QueryParams>) -> Json<serde_json::Value> {
+    // Candle length and the offset aligning buckets to it, weeks start on Monday
+    let (seconds, offset): (i64, i64) = match params.interval.as_deref().unwrap_or("1h") {
+        "1h" => (3600, 0),
+        "4h" => (4 * 3600, 0),
+        "1d" => (86400, 0),
+        "1w" => (7 * 86400, 4 * 86400),
+        _ => return Json(json!({ "error": "interval must be one of 1h, 4h, 1d, 1w" })),
+    };
+
+    match establish_connection().await {
+        Ok(client) => {
+            let pool = params.pool.as_deref().unwrap_or("BTC.BTC");
+
+            let mut filters = Vec::new();
+            if let Some(start_time) = params.start_time.as_deref().and_then(|t| t.parse::<i64>().ok()) {
+                filters.push(format!("bucket >= {}", start_time));
+            }
+            if let Some(end_time) = params.end_time.as_deref().and_then(|t| t.parse::<i64>().ok()) {
+                filters.push(format!("bucket + $3 <= {}", end_time));
+            }
+            let filters = if filters.is_empty() { String::new() } else { format!("WHERE {}", filters.join(" AND ")) };
+
+            let order = match params.order.as_deref() {
+                Some(order) if order.eq_ignore_ascii_case("asc") => "ASC",
+                _ => "DESC",
+            };
+            let limit = params.limit.unwrap_or(400);
+            let offset_rows = page_offset(params.page, limit);
+
+            let query = format!(
+                "WITH prices AS (
+                    SELECT (start_time::bigint - $2) / $3 * $3 + $2 AS bucket, start_time::bigint AS start_time,
+                           COALESCE(LAG(asset_price::numeric) OVER w, asset_price::numeric) AS open, asset_price::numeric AS close,
+                           COALESCE(LAG(asset_price_usd::numeric) OVER w, asset_price_usd::numeric) AS open_usd, asset_price_usd::numeric AS close_usd,
+                           GREATEST(inserted_at::bigint, LAG(inserted_at::bigint) OVER w) AS inserted_at
+                    FROM depth_intervals WHERE network = $4 AND pool = $1 AND asset_price IS NOT NULL WINDOW w AS (ORDER BY end_time::bigint)
+                ), volumes AS (
+                    SELECT (s.start_time::bigint - $2) / $3 * $3 + $2 AS bucket,
+                           ROUND(SUM(s.total_volume::numeric * d.rune_depth::numeric) / NULLIF(SUM(d.rune_depth::numeric), 0)) AS volume,
+                           ROUND(SUM(s.total_volume_usd::numeric * d.rune_depth::numeric) / NULLIF(SUM(d.rune_depth::numeric), 0), 8) AS volume_usd,
+                           SUM(s.total_volume::numeric) AS total_volume, SUM(s.total_volume_usd::numeric) AS total_volume_usd,
+                           MAX(GREATEST(s.inserted_at::bigint, d.inserted_at::bigint)) AS inserted_at
+                    FROM swap_history_intervals s
+                    LEFT JOIN depth_intervals d ON d.network = s.network AND d.pool = s.pool AND d.start_time = s.start_time
+                    WHERE s.network = $4 AND s.pool = $1 GROUP BY 1
+                ), candles AS (
+                    SELECT bucket,
+                           (array_agg(open ORDER BY start_time))[1] AS open, (array_agg(close ORDER BY start_time DESC))[1] AS close,
+                           MAX(GREATEST(open, close)) AS high, MIN(LEAST(open, close)) AS low,
+                           (array_agg(open_usd ORDER BY start_time))[1] AS open_usd, (array_agg(close_usd ORDER BY start_time DESC))[1] AS close_usd,
+                           MAX(GREATEST(open_usd, close_usd)) AS high_usd, MIN(LEAST(open_usd, close_usd)) AS low_usd,
+                           MAX(inserted_at) AS inserted_at
+                    FROM prices GROUP BY bucket
+                )
+                SELECT bucket, open::text, high::text, low::text, close::text, open_usd::text, high_usd::text, low_usd::text, close_usd::text,
+                       volume::text, volume_usd::text, total_volume::text, total_volume_usd::text,
+                       GREATEST(candles.inserted_at, volumes.inserted_at)::text AS inserted_at
+                FROM candles LEFT JOIN volumes USING (bucket)
+                {} ORDER BY bucket {} LIMIT {} OFFSET {}",
+                filters, order, limit, offset_rows
+            );
+
+            println!("Generated SQL Query: {}", query);
+
+            let rows = client.query(&query, &[&pool, &offset, &seconds, &params.network()]).await.unwrap();
+
+            let text = |row: &Row, column: &str| row.get::<_, Option<String>>(column);
+            let candles: Vec<Candle> = rows.iter().map(|row| {
+                let bucket: i64 = row.get("bucket");
+                Candle {
+                    close: text(row, "close").unwrap_or_default(),
+                    close_usd: text(row, "close_usd").unwrap_or_default(),
+                    end_time: (bucket + seconds).to_string(),
+                    high: text(row, "high").unwrap_or_default(),
+                    high_usd: text(row, "high_usd").unwrap_or_default(),
+                    low: text(row, "low").unwrap_or_default(),
+                    low_usd: text(row, "low_usd").unwrap_or_default(),
+                    open: text(row, "open").unwrap_or_default(),
+                    open_usd: text(row, "open_usd").unwrap_or_default(),
+                    pool: pool.to_string(),
+                    start_time: bucket.to_string(),
+                    total_volume: text(row, "total_volume"),
+                    total_volume_usd: text(row, "total_volume_usd"),
+                    volume: text(row, "volume"),
+                    // Rounded to 8 places in SQL, without the trailing zeros that leaves
+                    volume_usd: text(row, "volume_usd").map(|usd| Decimal::from_str(&usd).map(|usd| usd.normalize().to_string()).unwrap_or(usd)),
+                }
+            }).collect();
+
+            Json(json!({ "data": candles, "lastInsertedAt": last_inserted_at(&rows) }))
         }
         Err(e) => {
             eprintln!("Failed to connect to the database: {}", e);
@@ -480,3 +919,23 @@ pub async fn get_earning_history(Query(params): Query<QueryParams>) -> Json<serd
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(query: serde_json::Value) -> QueryParams {
+        serde_json::from_value(query).unwrap()
+    }
+
+    #[test]
+    fn sort_by_only_names_numeric_columns_of_the_table() {
+        let query = history_query("depth_intervals", &params(json!({ "sort_by": "asset_depth", "order": "asc" })), Vec::new());
+        assert!(query.contains(" ORDER BY asset_depth::numeric ASC NULLS LAST"));
+
+        for column in ["pool", "total_volume", "no_such_column"] {
+            let query = history_query("depth_intervals", &params(json!({ "sort_by": column })), Vec::new());
+            assert!(query.contains(" ORDER BY end_time::numeric DESC NULLS LAST"), "{}", query);
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    body::{Body, Bytes, Full},
+    extract::State,
+    http::{header, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::config::env_or;
+use crate::events::Events;
+
+const DEFAULT_CACHE_SIZE: usize = 1000;
+const DEFAULT_CACHE_TTL_SECS: u64 = 60;
+
+/// Responses above this size are served but not cached.
+const MAX_CACHED_BODY: usize = 4 * 1024 * 1024;
+
+struct CachedResponse {
+    body: Bytes,
+    content_type: Option<HeaderValue>,
+    datasets: Vec<String>,
+    stored_at: Instant,
+    last_used: u64,
+}
+
+struct Entries {
+    responses: HashMap<String, CachedResponse>,
+    // Incremented on each use, the entry with the lowest `last_used` is evicted first
+    clock: u64,
+    // Incremented on each invalidation, a response computed across one is not stored
+    generation: u64,
+}
+
+/// Successful responses of the dataset-backed endpoints, at most `RESPONSE_CACHE_SIZE`
+/// of them for `RESPONSE_CACHE_TTL_SECS`, dropped as soon as their dataset is ingested.
+#[derive(Clone)]
+pub struct ResponseCache {
+    entries: Arc<Mutex<Entries>>,
+    capacity: usize,
+    ttl: Duration,
+}
+
+impl ResponseCache {
+    /// Creates the cache and starts invalidating it on the intervals relayed by `events`.
+    pub fn start(events: &Events) -> ResponseCache {
+        let cache = ResponseCache {
+            entries: Arc::new(Mutex::new(Entries { responses: HashMap::new(), clock: 0, generation: 0 })),
+            capacity: env_or("RESPONSE_CACHE_SIZE", DEFAULT_CACHE_SIZE),
+            ttl: Duration::from_secs(env_or("RESPONSE_CACHE_TTL_SECS", DEFAULT_CACHE_TTL_SECS)),
+        };
+        tokio::spawn(invalidate_on_ingestion(cache.clone(), events.clone()));
+
+        cache
+    }
+
+    fn get(&self, key: &str) -> Option<Response> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+
+        let cached = entries.responses.get_mut(key)?;
+        if cached.stored_at.elapsed() > self.ttl {
+            entries.responses.remove(key);
+            return None;
+        }
+        cached.last_used = clock;
+
+        let mut response = Full::from(cached.body.clone()).into_response();
+        if let Some(content_type) = &cached.content_type {
+            response.headers_mut().insert(header::CONTENT_TYPE, content_type.clone());
+        }
+        Some(response)
+    }
+
+    /// The current invalidation generation, taken before computing a response to `insert`.
+    fn generation(&self) -> u64 {
+        self.entries.lock().unwrap().generation
+    }
+
+    /// Stores a response computed since `generation`, unless the cache was invalidated
+    /// meanwhile: it may have read the rows from before the ingestion that invalidated it.
+    fn insert(&self, key: String, body: Bytes, content_type: Option<HeaderValue>, datasets: Vec<String>, generation: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.generation != generation {
+            return;
+        }
+        if entries.responses.len() >= self.capacity && !entries.responses.contains_key(&key) {
+            let least_recent = entries.responses.iter().min_by_key(|(_, cached)| cached.last_used).map(|(key, _)| key.clone());
+            if let Some(least_recent) = least_recent {
+                entries.responses.remove(&least_recent);
+            }
+        }
+
+        entries.clock += 1;
+        let last_used = entries.clock;
+        entries.responses.insert(key, CachedResponse { body, content_type, datasets, stored_at: Instant::now(), last_used });
+    }
+
+    /// Drops the responses built from `dataset`, or every response when `None`.
+    fn invalidate(&self, dataset: Option<&str>) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.generation += 1;
+        match dataset {
+            Some(dataset) => entries.responses.retain(|_, cached| !cached.datasets.iter().any(|d| d == dataset)),
+            None => entries.responses.clear(),
+        }
+    }
+}
+
+async fn invalidate_on_ingestion(cache: ResponseCache, mut events: Events) {
+    let mut updates = events.intervals.subscribe();
+    loop {
+        let update = tokio::select! {
+            update = updates.recv() => update,
+            _ = events.shutdown.wait() => return,
+        };
+        match update {
+            Ok(update) => cache.invalidate(Some(&update.dataset)),
+            // Missed some, so any response may be stale
+            Err(RecvError::Lagged(_)) => cache.invalidate(None),
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Datasets a response of `path` is built from, `None` for what is not cached.
+fn datasets_for(path: &str, query: &[(&str, &str)]) -> Option<Vec<String>> {
+    let datasets: &[&str] = match path {
+        "/depth" | "/analytics/lp-performance" => &["depth"],
+        "/swap" => &["swap"],
+        "/earnings" => &["earnings"],
+        path if path.starts_with("/earnings/pools/") => &["earnings"],
+        "/rune" => &["rune"],
+        "/tvl" => &["tvl"],
+        "/liquidity" => &["liquidity"],
+        "/savers" => &["savers"],
+        "/candles" => &["depth", "swap"],
+        "/analytics/apy" => &["depth", "earnings"],
+        "/analytics/indicators" => {
+            // `earnings/pools` is ingested as `earnings`
+            let dataset = query.iter().find(|(name, _)| *name == "dataset")?.1;
+            return Some(vec![dataset.split('/').next().unwrap_or(dataset).to_string()]);
+        }
+        _ => return None,
+    };
+    Some(datasets.iter().map(|dataset| dataset.to_string()).collect())
+}
+
+/// Middleware caching the dataset-backed GET endpoints by path and sorted query parameters.
+pub async fn cache_responses(State(cache): State<ResponseCache>, request: Request<Body>, next: Next<Body>) -> Response {
+    if request.method() != Method::GET {
+        return next.run(request).await;
+    }
+
+    let path = request.uri().path().to_string();
+    let raw_query = request.uri().query().unwrap_or_default().to_string();
+    // Amounts are cached in base units
+    let mut query: Vec<(&str, &str)> = raw_query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+        .filter(|(name, _)| *name != "units")
+        .collect();
+    let Some(datasets) = datasets_for(&path, &query) else {
+        return next.run(request).await;
+    };
+    query.sort_unstable();
+    let key = format!("{}?{}", path, query.iter().map(|(name, value)| format!("{}={}", name, value)).collect::<Vec<_>>().join("&"));
+
+    if let Some(mut response) = cache.get(&key) {
+        response.headers_mut().insert("x-cache", HeaderValue::from_static("HIT"));
+        return response;
+    }
+
+    let generation = cache.generation();
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(e) => {
+            eprintln!("Failed to read response of {}: {}", path, e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    // Handlers report failures such as a lost database connection as `{"error": ...}`
+    if body.len() <= MAX_CACHED_BODY && !body.starts_with(b"{\"error\"") {
+        cache.insert(key, body.clone(), parts.headers.get(header::CONTENT_TYPE).cloned(), datasets, generation);
+    }
+
+    parts.headers.insert("x-cache", HeaderValue::from_static("MISS"));
+    Response::from_parts(parts, Full::from(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache() -> ResponseCache {
+        ResponseCache {
+            entries: Arc::new(Mutex::new(Entries { responses: HashMap::new(), clock: 0, generation: 0 })),
+            capacity: 10,
+            ttl: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn drops_a_response_computed_across_an_invalidation() {
+        let cache = cache();
+        let generation = cache.generation();
+        cache.invalidate(Some("depth"));
+        cache.insert("/depth?".to_string(), Bytes::from_static(b"{}"), None, vec!["depth".to_string()], generation);
+        assert!(cache.get("/depth?").is_none());
+
+        let generation = cache.generation();
+        cache.insert("/depth?".to_string(), Bytes::from_static(b"{}"), None, vec!["depth".to_string()], generation);
+        assert!(cache.get("/depth?").is_some());
+
+        cache.invalidate(Some("swap"));
+        assert!(cache.get("/depth?").is_some());
+        cache.invalidate(Some("depth"));
+        assert!(cache.get("/depth?").is_none());
+    }
+}
@@ -0,0 +1,129 @@
+use axum::{
+    body::{Body, Bytes, Full},
+    http::{header, HeaderMap, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Ranges ending before the latest stored interval no longer change. Private, as responses
+/// depend on the caller's API key, which shared caches do not key on.
+const CLOSED_RANGE_CACHE_CONTROL: &str = "private, max-age=86400";
+/// Ranges still growing are revalidated, which costs a 304 when nothing changed.
+const OPEN_RANGE_CACHE_CONTROL: &str = "private, no-cache";
+
+fn is_history(path: &str) -> bool {
+    matches!(
+        path,
+        "/depth" | "/swap" | "/earnings" | "/rune" | "/tvl" | "/liquidity" | "/savers" | "/candles" | "/analytics/indicators"
+    ) || path.starts_with("/earnings/pools/")
+}
+
+/// Latest `endTime` of the intervals in a `{"data": [...]}` body.
+fn latest_end_time(body: &Value) -> Option<i64> {
+    body.get("data")?
+        .as_array()?
+        .iter()
+        .filter_map(|interval| interval.get("endTime")?.as_str()?.parse::<i64>().ok())
+        .max()
+}
+
+/// When the newest of the intervals in a body was stored, as the handlers report it.
+fn last_inserted_at(body: &Value) -> Option<DateTime<Utc>> {
+    let time = body.get("lastInsertedAt")?.as_str()?.parse::<i64>().ok()?;
+    Utc.timestamp_opt(time, 0).single()
+}
+
+/// Whether an `If-None-Match` list matches `etag`, weakly as RFC 7232 has it for GET.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+fn not_modified(headers: &HeaderMap, etag: &str, last_modified: Option<DateTime<Utc>>) -> bool {
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH).and_then(|value| value.to_str().ok()) {
+        // Takes precedence over If-Modified-Since
+        return etag_matches(if_none_match, etag);
+    }
+
+    let if_modified_since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok());
+    match (if_modified_since, last_modified) {
+        (Some(since), Some(last_modified)) => last_modified.timestamp() <= since.timestamp(),
+        _ => false,
+    }
+}
+
+/// Middleware adding `ETag`, `Last-Modified` and `Cache-Control` to the history endpoints
+/// and answering `If-None-Match`/`If-Modified-Since` with a 304 when the intervals did not change.
+///
+/// The ETag hashes the body, so it changes with any interval Midgard revised or a backfill
+/// added. `Last-Modified` is when the newest of the returned rows was stored, which a
+/// backfill of older intervals moves too, unlike their `endTime`.
+pub async fn conditional_get(request: Request<Body>, next: Next<Body>) -> Response {
+    if request.method() != Method::GET || !is_history(request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let request_headers = request.headers().clone();
+    let end_bound = request.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("end_time="))
+            .and_then(|value| value.parse::<i64>().ok())
+    });
+
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body: Bytes = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(e) => {
+            eprintln!("Failed to read history response: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    // Failures are reported as `{"error": ...}` with a 200, never cache those
+    if body.starts_with(b"{\"error\"") {
+        return Response::from_parts(parts, Full::from(body)).into_response();
+    }
+
+    let etag = format!("\"{}\"", &hex::encode(Sha256::digest(&body))[..32]);
+    let json: Value = serde_json::from_slice(&body).unwrap_or_default();
+    let latest = latest_end_time(&json);
+    let last_modified = last_inserted_at(&json);
+    let closed = matches!((end_bound, latest), (Some(bound), Some(latest)) if bound <= latest);
+
+    let headers = &mut parts.headers;
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        headers.insert(header::ETAG, value);
+    }
+    if let Some(last_modified) = last_modified {
+        if let Ok(value) = HeaderValue::from_str(&last_modified.format(HTTP_DATE).to_string()) {
+            headers.insert(header::LAST_MODIFIED, value);
+        }
+    }
+    let cache_control = if closed { CLOSED_RANGE_CACHE_CONTROL } else { OPEN_RANGE_CACHE_CONTROL };
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static(cache_control));
+
+    if not_modified(&request_headers, &etag, last_modified) {
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers.remove(header::CONTENT_TYPE);
+        parts.headers.remove(header::CONTENT_LENGTH);
+        return Response::from_parts(parts, Full::from(Bytes::new())).into_response();
+    }
+
+    Response::from_parts(parts, Full::from(body)).into_response()
+}
@@ -0,0 +1,47 @@
+/// Reads an environment variable, falling back to `default` when it is unset or invalid.
+pub fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// The multichain chaosnet launch, where Midgard's history starts.
+const DEFAULT_HISTORY_START: i32 = 1618099200;
+
+/// Where feeds without stored rows start backfilling from, `HISTORY_START` in epoch seconds.
+pub fn history_start() -> i32 {
+    env_or("HISTORY_START", DEFAULT_HISTORY_START)
+}
+
+/// Network rows are stored under when nothing else is said, and the one API requests default to.
+pub const DEFAULT_NETWORK: &str = "mainnet";
+
+const DEFAULT_MIDGARD_URL: &str = "https://midgard.ninerealms.com";
+
+/// A THORChain network and the Midgard it is ingested from.
+#[derive(Debug, Clone)]
+pub struct Network {
+    pub name: String,
+    pub midgard_url: String,
+}
+
+/// Networks to ingest, from `NETWORKS` as `mainnet=https://...,stagenet=https://...`.
+/// Without it, only mainnet from `MIDGARD_URL`.
+pub fn networks() -> Vec<Network> {
+    let configured: Vec<Network> = std::env::var("NETWORKS")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|entry| entry.split_once('='))
+        .map(|(name, url)| Network { name: name.trim().to_string(), midgard_url: url.trim().to_string() })
+        .filter(|network| !network.name.is_empty() && !network.midgard_url.is_empty())
+        .collect();
+    if !configured.is_empty() {
+        return configured;
+    }
+
+    vec![Network {
+        name: DEFAULT_NETWORK.to_string(),
+        midgard_url: std::env::var("MIDGARD_URL").unwrap_or_else(|_| DEFAULT_MIDGARD_URL.to_string()),
+    }]
+}
@@ -1,53 +1,25 @@
 
 use postgres_native_tls::MakeTlsConnector;
-use tokio_postgres::{Client, Error};
-use crate::model::{DepthInterval,EarningInterval,Pool,RunePoolInterval,SwapsInterval};
-use reqwest::Error as ReqwestError;
-use tokio_postgres::Error as PostgresError;
-use std::fmt;
+use futures_util::StreamExt;
+use tokio::sync::mpsc;
+use tokio_postgres::{AsyncMessage, Client, Error, Notification, Row, Transaction};
+use crate::config::history_start;
+use crate::model::{Action,Churn,DepthInterval,EarningInterval,LiquidityChangeInterval,NetworkSnapshot,Pool,PoolSnapshot,RunePoolInterval,SaverInterval,SwapsInterval,TvlInterval};
 use native_tls::TlsConnector;
 use thiserror::Error;
 
-#[derive(Debug)]
-pub enum AppError {
-    Reqwest(ReqwestError),
-    Postgres(PostgresError),
-}
-
 #[derive(Error, Debug)]
-pub enum MyError {
-    #[error("Request error: {0}")]
+pub enum AppError {
+    #[error("Reqwest error: {0}")]
     Reqwest(#[from] reqwest::Error),
 
-    #[error("Database error: {0}")]
+    #[error("Postgres error: {0}")]
     Postgres(#[from] tokio_postgres::Error),
 
     #[error("Serialization error: {0}")]
     SerdeJson(#[from] serde_json::Error),
 }
 
-impl fmt::Display for AppError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            AppError::Reqwest(e) => write!(f, "Reqwest error: {}", e),
-            AppError::Postgres(e) => write!(f, "Postgres error: {}", e),
-        }
-    }
-}
-
-impl std::error::Error for AppError {}
-
-impl From<ReqwestError> for AppError {
-    fn from(error: ReqwestError) -> Self {
-        AppError::Reqwest(error)
-    }
-}
-
-impl From<PostgresError> for AppError {
-    fn from(error: PostgresError) -> Self {
-        AppError::Postgres(error)
-    }
-}
 pub async fn establish_connection() -> Result<Client, Error> {
     dotenv::dotenv().ok();
     // Adjust the connection string here to remove sslmode=require
@@ -67,272 +39,438 @@ pub async fn establish_connection() -> Result<Client, Error> {
     Ok(client)
 }
 
-pub async fn fetch_depth_data(from: i32, count: i32) -> Vec<DepthInterval> {
-    let url = format!(
-        "https://midgard.ninerealms.com/v2/history/depths/BTC.BTC?interval=hour&count={}&from={}",
-        count, from
-    );
-    println!("Fetching depth data from URL: {}", url);
-
-    let response = match reqwest::get(&url).await {
-        Ok(resp) => resp,
-        Err(err) => {
-            println!("Error fetching depth data from URL: {}", err);
-            return Vec::new();
-        }
-    };
+/// Opens a connection LISTENing on `channel`. The receiver ends when the connection drops,
+/// which also happens when the returned client is dropped.
+pub async fn establish_listener(channel: &str) -> Result<(Client, mpsc::UnboundedReceiver<Notification>), Error> {
+    dotenv::dotenv().ok();
 
-    let json_response: serde_json::Value = match response.json().await {
-        Ok(json) => json,
-        Err(err) => {
-            println!("Error parsing depth response JSON: {}", err);
-            return Vec::new();
-        }
-    };
+    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL not set");
+    let connector = TlsConnector::builder().build().unwrap();
+    let connector = MakeTlsConnector::new(connector);
+    let (client, mut connection) = tokio_postgres::connect(&database_url, connector).await?;
 
-    match serde_json::from_value(json_response["intervals"].to_owned()) {
-        Ok(output_vec) => output_vec,
-        Err(err) => {
-            println!("Error deserializing depth data: {}", err);
-            Vec::new() 
+    // Notifications only arrive through the connection's message stream
+    let (sender, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let mut messages = futures_util::stream::poll_fn(move |cx| connection.poll_message(cx));
+        while let Some(message) = messages.next().await {
+            match message {
+                Ok(AsyncMessage::Notification(notification)) => {
+                    if sender.send(notification).is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("Connection error: {}", e);
+                    break;
+                }
+            }
         }
-    }
+    });
+
+    client.batch_execute(&format!("LISTEN {}", channel)).await?;
+    Ok((client, receiver))
 }
 
+/// Creates any missing tables, see `schema.sql`.
+pub async fn ensure_schema(client: &Client) -> Result<(), Error> {
+    client.batch_execute(include_str!("../schema.sql")).await
+}
 
-pub async fn fetch_swaps_data(from: i32, count: i32) -> Vec<SwapsInterval> {
-    let url = format!(
-        "https://midgard.ninerealms.com/v2/history/swaps?interval=hour&count={}&from={}",
-        count, from
-    );
-    println!("Fetching swaps data from URL: {}", url);
+/// Returns the `date` (nanoseconds) actions are synced from: the oldest action still pending
+/// at most `pending_window` seconds before the newest stored one, or else the newest one.
+pub async fn fetch_action_cursor(client: &Client, network: &str, pending_window: i64) -> Result<Option<i64>, Error> {
+    let row = client
+        .query_one(
+            "WITH newest AS (SELECT MAX(date::numeric) AS date FROM actions WHERE network = $1)
+            SELECT COALESCE(
+                (SELECT MIN(a.date::numeric) FROM actions a, newest
+                WHERE a.network = $1 AND a.status = 'pending' AND a.date::numeric >= newest.date - $2::bigint * 1000000000),
+                newest.date
+            )::bigint AS date FROM newest",
+            &[&network, &pending_window],
+        )
+        .await?;
+    Ok(row.get("date"))
+}
 
-    let response = match reqwest::get(&url).await {
-        Ok(resp) => resp,
-        Err(err) => {
-            println!("Error fetching swaps data from URL: {}", err);
-            return Vec::new();
+/// Returns the latest stored `end_time` of an interval table, or `HISTORY_START` when it is empty.
+/// Tables with a `pool` column keep a cursor per pool.
+pub async fn fetch_cursor(client: &Client, table: &str, network: &str, pool: Option<&str>) -> Result<i32, Error> {
+    let row = match pool {
+        Some(pool) => {
+            client
+                .query_one(&format!("SELECT MAX(end_time::bigint)::int AS end_time FROM {} WHERE network = $1 AND pool = $2", table), &[&network, &pool])
+                .await?
         }
-    };
-
-    let json_response: serde_json::Value = match response.json().await {
-        Ok(json) => json,
-        Err(err) => {
-            println!("Error parsing swaps response JSON: {}", err);
-            return Vec::new(); 
+        None => {
+            client
+                .query_one(&format!("SELECT MAX(end_time::bigint)::int AS end_time FROM {} WHERE network = $1", table), &[&network])
+                .await?
         }
     };
+    let end_time: Option<i32> = row.get("end_time");
 
-    match serde_json::from_value(json_response["intervals"].to_owned()) {
-        Ok(output_vec) => output_vec,
-        Err(err) => {
-            println!("Error deserializing swaps data: {}", err);
-            Vec::new()
-        }
-    }
+    Ok(end_time.unwrap_or_else(history_start))
 }
 
-
-pub async fn fetch_earnings_data(from: i32, count: i32) -> Vec<EarningInterval> {
-    let url = format!(
-        "https://midgard.ninerealms.com/v2/history/earnings?interval=hour&count={}&from={}",
-        count, from
-    );
-    println!("Fetching earnings from URL: {}", url);
-
-    let response = match reqwest::get(&url).await {
-        Ok(resp) => resp,
-        Err(err) => {
-            println!("Error fetching data from the URL: {}", err);
-            return Vec::new(); 
-        }
-    };
-
-    let json_response: serde_json::Value = match response.json().await {
-        Ok(json) => json,
-        Err(err) => {
-            println!("Error parsing response JSON: {}", err);
-            return Vec::new();
-        }
-    };
-    match serde_json::from_value(json_response["intervals"].to_owned()) {
-        Ok(output_vec) => output_vec,
-        Err(err) => {
-            println!("Error deserializing JSON into Vec<EarningInterval>: {}", err);
-            Vec::new() 
-        }
-    }
+/// Collects one field of every row into a column array for `UNNEST`.
+fn column<'a, T>(rows: &'a [T], field: impl Fn(&'a T) -> &'a str) -> Vec<&'a str> {
+    rows.iter().map(field).collect()
 }
 
-
-pub async fn fetch_runepool_data(from: i32, count: i32) -> Vec<RunePoolInterval> {
-    let url = format!(
-        "https://midgard.ninerealms.com/v2/history/runepool?interval=hour&count={}&from={}",
-        count, from
-    );
-    println!("Fetching rune pool data from URL: {}", url);
-
-    let response = match reqwest::get(&url).await {
-        Ok(resp) => resp,
-        Err(err) => {
-            println!("Error fetching rune pool data from URL: {}", err);
-            return Vec::new(); 
-        }
-    };
-
-    let json_response: serde_json::Value = match response.json().await {
-        Ok(json) => json,
-        Err(err) => {
-            println!("Error parsing rune pool response JSON: {}", err);
-            return Vec::new(); 
-        }
-    };
-
-    match serde_json::from_value(json_response["intervals"].to_owned()) {
-        Ok(output_vec) => output_vec,
-        Err(err) => {
-            println!("Error deserializing rune pool data: {}", err);
-            Vec::new() 
-        }
-    }
+/// The `end_time` of each row an interval insert `RETURNING end_time` stored, conflicting rows are not returned.
+fn inserted_end_times(rows: &[Row]) -> Vec<i64> {
+    rows.iter().filter_map(|row| row.get::<_, &str>("end_time").parse().ok()).collect()
 }
 
+fn optional_column<'a, T>(rows: &'a [T], field: impl Fn(&'a T) -> Option<&'a str>) -> Vec<Option<&'a str>> {
+    rows.iter().map(field).collect()
+}
 
-pub async fn insert_depth_interval(client: &Client, depth: &DepthInterval) -> Result<(), Error> {
-    client.execute(
-        "INSERT INTO depth_intervals (asset_depth, asset_price, asset_price_usd, end_time, liquidity_units, luvi, members_count, rune_depth, start_time, synth_supply, synth_units, units) 
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) 
-        ON CONFLICT (end_time) DO NOTHING;",
+pub async fn insert_depth_intervals(tx: &Transaction<'_>, network: &str, depths: &[DepthInterval]) -> Result<Vec<i64>, Error> {
+    let rows = tx.query(
+        "INSERT INTO depth_intervals (network, asset_depth, asset_price, asset_price_usd, end_time, liquidity_units, luvi, members_count, pool, rune_depth, start_time, synth_supply, synth_units, units) 
+        SELECT $14, * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::text[], $6::text[], $7::text[], $8::text[], $9::text[], $10::text[], $11::text[], $12::text[], $13::text[]) 
+        ON CONFLICT (network, pool, end_time) DO NOTHING
+        RETURNING end_time",
         &[
-            &depth.asset_depth,
-            &depth.asset_price,
-            &depth.asset_price_usd,
-            &depth.end_time,
-            &depth.liquidity_units,
-            &depth.luvi,
-            &depth.members_count,
-            &depth.rune_depth,
-            &depth.start_time,
-            &depth.synth_supply,
-            &depth.synth_units,
-            &depth.units,
+            &column(depths, |d| &d.asset_depth),
+            &column(depths, |d| &d.asset_price),
+            &column(depths, |d| &d.asset_price_usd),
+            &column(depths, |d| &d.end_time),
+            &column(depths, |d| &d.liquidity_units),
+            &column(depths, |d| &d.luvi),
+            &column(depths, |d| &d.members_count),
+            &column(depths, |d| &d.pool),
+            &column(depths, |d| &d.rune_depth),
+            &column(depths, |d| &d.start_time),
+            &column(depths, |d| &d.synth_supply),
+            &column(depths, |d| &d.synth_units),
+            &column(depths, |d| &d.units),
+            &network,
         ],
     ).await?;
-    Ok(())
+    Ok(inserted_end_times(&rows))
 }
 
-pub async fn insert_swaps_interval(client: &Client, swap: &SwapsInterval) -> Result<(), Error> {
-    client.execute(
-        "INSERT INTO swap_history_intervals (average_slip, end_time, from_trade_average_slip, from_trade_count, from_trade_fees, from_trade_volume, from_trade_volume_usd, rune_price_usd, start_time, synth_mint_average_slip, synth_mint_count, synth_mint_fees, synth_mint_volume, synth_mint_volume_usd, synth_redeem_average_slip, synth_redeem_count, synth_redeem_fees, synth_redeem_volume, synth_redeem_volume_usd, to_asset_average_slip, to_asset_count, to_asset_fees, to_asset_volume, to_asset_volume_usd, to_rune_average_slip, to_rune_count, to_rune_fees, to_rune_volume, to_rune_volume_usd, total_count, total_fees, total_volume, total_volume_usd) 
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30, $31, $32, $33) 
-        ON CONFLICT (end_time) DO NOTHING ;",
+pub async fn insert_swaps_intervals(tx: &Transaction<'_>, network: &str, swaps: &[SwapsInterval]) -> Result<Vec<i64>, Error> {
+    let rows = tx.query(
+        "INSERT INTO swap_history_intervals (network, average_slip, end_time, from_trade_average_slip, from_trade_count, from_trade_fees, from_trade_volume, from_trade_volume_usd, pool, rune_price_usd, start_time, synth_mint_average_slip, synth_mint_count, synth_mint_fees, synth_mint_volume, synth_mint_volume_usd, synth_redeem_average_slip, synth_redeem_count, synth_redeem_fees, synth_redeem_volume, synth_redeem_volume_usd, to_asset_average_slip, to_asset_count, to_asset_fees, to_asset_volume, to_asset_volume_usd, to_rune_average_slip, to_rune_count, to_rune_fees, to_rune_volume, to_rune_volume_usd, total_count, total_fees, total_volume, total_volume_usd) 
+        SELECT $35, * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::text[], $6::text[], $7::text[], $8::text[], $9::text[], $10::text[], $11::text[], $12::text[], $13::text[], $14::text[], $15::text[], $16::text[], $17::text[], $18::text[], $19::text[], $20::text[], $21::text[], $22::text[], $23::text[], $24::text[], $25::text[], $26::text[], $27::text[], $28::text[], $29::text[], $30::text[], $31::text[], $32::text[], $33::text[], $34::text[]) 
+        ON CONFLICT (network, pool, end_time) DO NOTHING
+        RETURNING end_time",
         &[
-            &swap.average_slip,
-            &swap.end_time,
-            &swap.from_trade_average_slip,
-            &swap.from_trade_count,
-            &swap.from_trade_fees,
-            &swap.from_trade_volume,
-            &swap.from_trade_volume_usd,
-            &swap.rune_price_usd,
-            &swap.start_time,
-            &swap.synth_mint_average_slip,
-            &swap.synth_mint_count,
-            &swap.synth_mint_fees,
-            &swap.synth_mint_volume,
-            &swap.synth_mint_volume_usd,
-            &swap.synth_redeem_average_slip,
-            &swap.synth_redeem_count,
-            &swap.synth_redeem_fees,
-            &swap.synth_redeem_volume,
-            &swap.synth_redeem_volume_usd,
-            &swap.to_asset_average_slip,
-            &swap.to_asset_count,
-            &swap.to_asset_fees,
-            &swap.to_asset_volume,
-            &swap.to_asset_volume_usd,
-            &swap.to_rune_average_slip,
-            &swap.to_rune_count,
-            &swap.to_rune_fees,
-            &swap.to_rune_volume,
-            &swap.to_rune_volume_usd,
-            &swap.total_count,
-            &swap.total_fees,
-            &swap.total_volume,
-            &swap.total_volume_usd,
+            &column(swaps, |s| &s.average_slip),
+            &column(swaps, |s| &s.end_time),
+            &column(swaps, |s| &s.from_trade_average_slip),
+            &column(swaps, |s| &s.from_trade_count),
+            &column(swaps, |s| &s.from_trade_fees),
+            &column(swaps, |s| &s.from_trade_volume),
+            &column(swaps, |s| &s.from_trade_volume_usd),
+            &column(swaps, |s| &s.pool),
+            &column(swaps, |s| &s.rune_price_usd),
+            &column(swaps, |s| &s.start_time),
+            &column(swaps, |s| &s.synth_mint_average_slip),
+            &column(swaps, |s| &s.synth_mint_count),
+            &column(swaps, |s| &s.synth_mint_fees),
+            &column(swaps, |s| &s.synth_mint_volume),
+            &column(swaps, |s| &s.synth_mint_volume_usd),
+            &column(swaps, |s| &s.synth_redeem_average_slip),
+            &column(swaps, |s| &s.synth_redeem_count),
+            &column(swaps, |s| &s.synth_redeem_fees),
+            &column(swaps, |s| &s.synth_redeem_volume),
+            &column(swaps, |s| &s.synth_redeem_volume_usd),
+            &column(swaps, |s| &s.to_asset_average_slip),
+            &column(swaps, |s| &s.to_asset_count),
+            &column(swaps, |s| &s.to_asset_fees),
+            &column(swaps, |s| &s.to_asset_volume),
+            &column(swaps, |s| &s.to_asset_volume_usd),
+            &column(swaps, |s| &s.to_rune_average_slip),
+            &column(swaps, |s| &s.to_rune_count),
+            &column(swaps, |s| &s.to_rune_fees),
+            &column(swaps, |s| &s.to_rune_volume),
+            &column(swaps, |s| &s.to_rune_volume_usd),
+            &column(swaps, |s| &s.total_count),
+            &column(swaps, |s| &s.total_fees),
+            &column(swaps, |s| &s.total_volume),
+            &column(swaps, |s| &s.total_volume_usd),
+            &network,
         ],
     ).await?;
-    Ok(())
+    Ok(inserted_end_times(&rows))
 }
 
-pub async fn insert_earning_interval(client: &Client, earning: &EarningInterval) -> Result<(), tokio_postgres::Error> {
-    let row = client
-        .query_opt(
-            "INSERT INTO earning_intervals (avg_node_count, block_rewards, bonding_earnings, earnings, end_time, liquidity_earnings, liquidity_fees, rune_price_usd, start_time) 
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
-            ON CONFLICT (end_time) DO NOTHING 
-            RETURNING id",
+pub async fn insert_earning_intervals(tx: &Transaction<'_>, network: &str, earnings: &[EarningInterval]) -> Result<Vec<i64>, Error> {
+    let rows = tx
+        .query(
+            "INSERT INTO earning_intervals (network, avg_node_count, block_rewards, bonding_earnings, earnings, end_time, liquidity_earnings, liquidity_fees, rune_price_usd, start_time) 
+            SELECT $10, * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::text[], $6::text[], $7::text[], $8::text[], $9::text[])
+            ON CONFLICT (network, end_time) DO NOTHING 
+            RETURNING id, end_time",
             &[
-                &earning.avg_node_count,
-                &earning.block_rewards,
-                &earning.bonding_earnings,
-                &earning.earnings,
-                &earning.end_time,
-                &earning.liquidity_earnings,
-                &earning.liquidity_fees,
-                &earning.rune_price_usd,
-                &earning.start_time,
+                &column(earnings, |e| &e.avg_node_count),
+                &column(earnings, |e| &e.block_rewards),
+                &column(earnings, |e| &e.bonding_earnings),
+                &column(earnings, |e| &e.earnings),
+                &column(earnings, |e| &e.end_time),
+                &column(earnings, |e| &e.liquidity_earnings),
+                &column(earnings, |e| &e.liquidity_fees),
+                &column(earnings, |e| &e.rune_price_usd),
+                &column(earnings, |e| &e.start_time),
+                &network,
             ],
         )
         .await?;
 
-    // Check if a row was returned
-    if let Some(row) = row {
-        let interval_id: i32 = row.get(0); // Get the id of the inserted row
-
-        // Insert pools associated with this earning interval
-        for pool in &earning.pools {
-            insert_pool(client, pool, interval_id).await?;
+    // Pools are only inserted for intervals that were new, conflicting intervals already have theirs
+    let mut interval_ids = Vec::new();
+    let mut pools = Vec::new();
+    for row in &rows {
+        let interval_id: i32 = row.get("id");
+        let end_time: &str = row.get("end_time");
+        if let Some(earning) = earnings.iter().find(|e| e.end_time == end_time) {
+            for pool in &earning.pools {
+                interval_ids.push(interval_id);
+                pools.push(pool);
+            }
         }
-    } else {
-        // Handle the case where no row was inserted due to conflict
-        println!("No new row inserted for end_time: {}", earning.end_time);
     }
+    insert_pools(tx, &interval_ids, &pools).await?;
 
-    Ok(()) // Return Ok if everything was successful
+    Ok(inserted_end_times(&rows))
 }
 
-
-pub async fn insert_pool(client: &Client, pool: &Pool ,interval_id: i32) -> Result<(), Error> {
-    client.execute(
+async fn insert_pools(tx: &Transaction<'_>, interval_ids: &[i32], pools: &[&Pool]) -> Result<u64, Error> {
+    tx.execute(
         "INSERT INTO pools (interval_id,asset_liquidity_fees, earnings, pool, rewards, rune_liquidity_fees, saver_earning, total_liquidity_fees_rune) 
-        VALUES ($1, $2, $3, $4, $5, $6, $7,$8) 
+        SELECT * FROM UNNEST($1::int[], $2::text[], $3::text[], $4::text[], $5::text[], $6::text[], $7::text[], $8::text[]) 
         ",
         &[
-            &interval_id,
-            &pool.asset_liquidity_fees,
-            &pool.earnings,
-            &pool.pool,
-            &pool.rewards,
-            &pool.rune_liquidity_fees,
-            &pool.saver_earning,
-            &pool.total_liquidity_fees_rune,
+            &interval_ids,
+            &optional_column(pools, |p| p.asset_liquidity_fees.as_deref()),
+            &optional_column(pools, |p| p.earnings.as_deref()),
+            &optional_column(pools, |p| p.pool.as_deref()),
+            &optional_column(pools, |p| p.rewards.as_deref()),
+            &optional_column(pools, |p| p.rune_liquidity_fees.as_deref()),
+            &optional_column(pools, |p| p.saver_earning.as_deref()),
+            &optional_column(pools, |p| p.total_liquidity_fees_rune.as_deref()),
+        ],
+    ).await
+}
+
+pub async fn insert_runepool_intervals(tx: &Transaction<'_>, network: &str, runepools: &[RunePoolInterval]) -> Result<Vec<i64>, Error> {
+    let rows = tx.query(
+        "INSERT INTO rune_pool_intervals (network, count, end_time, start_time, units) 
+        SELECT $5, * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[]) 
+        ON CONFLICT (network, end_time) DO NOTHING
+        RETURNING end_time",
+        &[
+            &column(runepools, |r| &r.count),
+            &column(runepools, |r| &r.end_time),
+            &column(runepools, |r| &r.start_time),
+            &column(runepools, |r| &r.units),
+            &network,
+        ],
+    ).await?;
+    Ok(inserted_end_times(&rows))
+}
+
+pub async fn insert_tvl_intervals(tx: &Transaction<'_>, network: &str, tvls: &[TvlInterval]) -> Result<Vec<i64>, AppError> {
+    let pools_depth = tvls
+        .iter()
+        .map(|t| serde_json::to_string(&t.pools_depth))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let rows = tx.query(
+        "INSERT INTO tvl_intervals (network, end_time, pools_depth, rune_price_usd, start_time, total_value_bonded, total_value_locked, total_value_locked_usd, total_value_pooled) 
+        SELECT $9, * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::text[], $6::text[], $7::text[], $8::text[]) 
+        ON CONFLICT (network, end_time) DO NOTHING
+        RETURNING end_time",
+        &[
+            &column(tvls, |t| &t.end_time),
+            &pools_depth,
+            &optional_column(tvls, |t| t.rune_price_usd.as_deref()),
+            &column(tvls, |t| &t.start_time),
+            &optional_column(tvls, |t| t.total_value_bonded.as_deref()),
+            &optional_column(tvls, |t| t.total_value_locked.as_deref()),
+            &optional_column(tvls, |t| t.total_value_locked_usd.as_deref()),
+            &column(tvls, |t| &t.total_value_pooled),
+            &network,
         ],
     ).await?;
-    Ok(())
+    Ok(inserted_end_times(&rows))
 }
 
-pub async fn insert_runepool_interval(client: &Client, runepool: &RunePoolInterval) -> Result<(), Error> {
-    client.execute(
-        "INSERT INTO rune_pool_intervals (count, end_time, start_time, units) 
-        VALUES ($1, $2, $3, $4) 
-        ON CONFLICT (end_time) DO NOTHING ;",
+pub async fn insert_liquidity_change_intervals(tx: &Transaction<'_>, network: &str, changes: &[LiquidityChangeInterval]) -> Result<Vec<i64>, Error> {
+    let rows = tx.query(
+        "INSERT INTO liquidity_change_intervals (network, add_asset_liquidity_volume, add_liquidity_count, add_liquidity_volume, add_liquidity_volume_usd, add_rune_liquidity_volume, end_time, impermanent_loss_protection_paid, net, pool, rune_price_usd, start_time, withdraw_asset_volume, withdraw_count, withdraw_rune_volume, withdraw_volume, withdraw_volume_usd) 
+        SELECT $17, * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::text[], $6::text[], $7::text[], $8::text[], $9::text[], $10::text[], $11::text[], $12::text[], $13::text[], $14::text[], $15::text[], $16::text[]) 
+        ON CONFLICT (network, pool, end_time) DO NOTHING
+        RETURNING end_time",
         &[
-            &runepool.count,
-            &runepool.end_time,
-            &runepool.start_time,
-            &runepool.units,
+            &column(changes, |c| &c.add_asset_liquidity_volume),
+            &column(changes, |c| &c.add_liquidity_count),
+            &column(changes, |c| &c.add_liquidity_volume),
+            &column(changes, |c| &c.add_liquidity_volume_usd),
+            &column(changes, |c| &c.add_rune_liquidity_volume),
+            &column(changes, |c| &c.end_time),
+            &optional_column(changes, |c| c.impermanent_loss_protection_paid.as_deref()),
+            &column(changes, |c| &c.net),
+            &column(changes, |c| &c.pool),
+            &column(changes, |c| &c.rune_price_usd),
+            &column(changes, |c| &c.start_time),
+            &column(changes, |c| &c.withdraw_asset_volume),
+            &column(changes, |c| &c.withdraw_count),
+            &column(changes, |c| &c.withdraw_rune_volume),
+            &column(changes, |c| &c.withdraw_volume),
+            &column(changes, |c| &c.withdraw_volume_usd),
+            &network,
         ],
     ).await?;
-    Ok(())
+    Ok(inserted_end_times(&rows))
+}
+
+pub async fn insert_saver_intervals(tx: &Transaction<'_>, network: &str, savers: &[SaverInterval]) -> Result<Vec<i64>, Error> {
+    let rows = tx.query(
+        "INSERT INTO saver_intervals (network, end_time, pool, savers_count, savers_depth, savers_units, start_time) 
+        SELECT $7, * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::text[], $6::text[]) 
+        ON CONFLICT (network, pool, end_time) DO NOTHING
+        RETURNING end_time",
+        &[
+            &column(savers, |s| &s.end_time),
+            &column(savers, |s| &s.pool),
+            &column(savers, |s| &s.savers_count),
+            &column(savers, |s| &s.savers_depth),
+            &column(savers, |s| &s.savers_units),
+            &column(savers, |s| &s.start_time),
+            &network,
+        ],
+    ).await?;
+    Ok(inserted_end_times(&rows))
+}
+
+pub async fn insert_pool_snapshots(tx: &Transaction<'_>, network: &str, snapshots: &[PoolSnapshot]) -> Result<u64, Error> {
+    tx.execute(
+        "INSERT INTO pool_snapshots (network, annual_percentage_rate, asset, asset_depth, asset_price, asset_price_usd, liquidity_units, pool_apy, rune_depth, savers_apr, savers_depth, savers_units, snapshot_time, status, synth_supply, synth_units, units, volume24h, average_slip, swap_count, swap_volume, total_fees, unique_member_count, unique_swapper_count) 
+        SELECT $24, * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::text[], $6::text[], $7::text[], $8::text[], $9::text[], $10::text[], $11::text[], $12::text[], $13::text[], $14::text[], $15::text[], $16::text[], $17::text[], $18::text[], $19::text[], $20::text[], $21::text[], $22::text[], $23::text[]) 
+        ON CONFLICT (network, asset, snapshot_time) DO NOTHING ;",
+        &[
+            &column(snapshots, |s| &s.annual_percentage_rate),
+            &column(snapshots, |s| &s.asset),
+            &column(snapshots, |s| &s.asset_depth),
+            &column(snapshots, |s| &s.asset_price),
+            &column(snapshots, |s| &s.asset_price_usd),
+            &column(snapshots, |s| &s.liquidity_units),
+            &column(snapshots, |s| &s.pool_apy),
+            &column(snapshots, |s| &s.rune_depth),
+            &optional_column(snapshots, |s| s.savers_apr.as_deref()),
+            &optional_column(snapshots, |s| s.savers_depth.as_deref()),
+            &optional_column(snapshots, |s| s.savers_units.as_deref()),
+            &column(snapshots, |s| &s.snapshot_time),
+            &column(snapshots, |s| &s.status),
+            &column(snapshots, |s| &s.synth_supply),
+            &column(snapshots, |s| &s.synth_units),
+            &column(snapshots, |s| &s.units),
+            &column(snapshots, |s| &s.volume24h),
+            &optional_column(snapshots, |s| s.stats.average_slip.as_deref()),
+            &optional_column(snapshots, |s| s.stats.swap_count.as_deref()),
+            &optional_column(snapshots, |s| s.stats.swap_volume.as_deref()),
+            &optional_column(snapshots, |s| s.stats.total_fees.as_deref()),
+            &optional_column(snapshots, |s| s.stats.unique_member_count.as_deref()),
+            &optional_column(snapshots, |s| s.stats.unique_swapper_count.as_deref()),
+            &network,
+        ],
+    ).await
+}
+
+/// Upserts actions, pending ones are updated once Midgard reports them settled.
+pub async fn insert_actions(tx: &Transaction<'_>, network: &str, actions: &[Action]) -> Result<u64, AppError> {
+    let to_json = |field: fn(&Action) -> String| actions.iter().map(field).collect::<Vec<_>>();
+    let addresses = to_json(|a| serde_json::to_string(&a.addresses).unwrap_or_default());
+    let in_coins = to_json(|a| serde_json::to_string(&a.in_coins).unwrap_or_default());
+    let network_fees = to_json(|a| serde_json::to_string(&a.network_fees).unwrap_or_default());
+    let out_coins = to_json(|a| serde_json::to_string(&a.out_coins).unwrap_or_default());
+    let pools = to_json(|a| serde_json::to_string(&a.pools).unwrap_or_default());
+    let streaming_swap_meta = actions
+        .iter()
+        .map(|a| a.streaming_swap_meta.as_ref().map(|meta| meta.to_string()))
+        .collect::<Vec<_>>();
+    let is_streaming_swap = actions.iter().map(|a| a.is_streaming_swap).collect::<Vec<_>>();
+
+    let inserted = tx.execute(
+        "INSERT INTO actions (network, action_type, addresses, affiliate_fee, date, height, in_coins, is_streaming_swap, liquidity_fee, liquidity_units, network_fees, out_coins, pools, status, streaming_swap_meta, swap_slip, tx_id) 
+        SELECT $17, a.action_type, ARRAY(SELECT jsonb_array_elements_text(a.addresses::jsonb)), a.affiliate_fee, a.date, a.height, a.in_coins, a.is_streaming_swap, a.liquidity_fee, a.liquidity_units, a.network_fees, a.out_coins, ARRAY(SELECT jsonb_array_elements_text(a.pools::jsonb)), a.status, a.streaming_swap_meta, a.swap_slip, a.tx_id 
+        FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::text[], $6::text[], $7::bool[], $8::text[], $9::text[], $10::text[], $11::text[], $12::text[], $13::text[], $14::text[], $15::text[], $16::text[]) 
+        AS a (action_type, addresses, affiliate_fee, date, height, in_coins, is_streaming_swap, liquidity_fee, liquidity_units, network_fees, out_coins, pools, status, streaming_swap_meta, swap_slip, tx_id) 
+        ON CONFLICT (network, tx_id, action_type, date) DO UPDATE SET 
+            addresses = EXCLUDED.addresses, 
+            liquidity_fee = EXCLUDED.liquidity_fee, 
+            liquidity_units = EXCLUDED.liquidity_units, 
+            network_fees = EXCLUDED.network_fees, 
+            out_coins = EXCLUDED.out_coins, 
+            status = EXCLUDED.status, 
+            streaming_swap_meta = EXCLUDED.streaming_swap_meta, 
+            swap_slip = EXCLUDED.swap_slip 
+        WHERE actions.status IS DISTINCT FROM EXCLUDED.status OR actions.out_coins IS DISTINCT FROM EXCLUDED.out_coins ;",
+        &[
+            &column(actions, |a| &a.action_type),
+            &addresses,
+            &optional_column(actions, |a| a.affiliate_fee.as_deref()),
+            &column(actions, |a| &a.date),
+            &column(actions, |a| &a.height),
+            &in_coins,
+            &is_streaming_swap,
+            &optional_column(actions, |a| a.liquidity_fee.as_deref()),
+            &optional_column(actions, |a| a.liquidity_units.as_deref()),
+            &network_fees,
+            &out_coins,
+            &pools,
+            &column(actions, |a| &a.status),
+            &streaming_swap_meta,
+            &optional_column(actions, |a| a.swap_slip.as_deref()),
+            &column(actions, |a| &a.tx_id),
+            &network,
+        ],
+    ).await?;
+    Ok(inserted)
+}
+
+pub async fn insert_network_snapshot(tx: &Transaction<'_>, network_name: &str, network: &NetworkSnapshot) -> Result<u64, Error> {
+    tx.execute(
+        "INSERT INTO network_snapshots (network, active_node_count, block_reward, bond_reward, bonding_apy, liquidity_apy, next_churn_height, pool_reward, pool_share_factor, snapshot_time, standby_node_count, total_active_bond, total_pooled_rune, total_reserve, total_standby_bond) 
+        VALUES ($15, $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14) 
+        ON CONFLICT (network, snapshot_time) DO NOTHING ;",
+        &[
+            &network.active_node_count,
+            &network.block_reward,
+            &network.bond_reward,
+            &network.bonding_apy,
+            &network.liquidity_apy,
+            &network.next_churn_height,
+            &network.pool_reward,
+            &network.pool_share_factor,
+            &network.snapshot_time,
+            &network.standby_node_count,
+            &network.total_active_bond,
+            &network.total_pooled_rune,
+            &network.total_reserve,
+            &network.total_standby_bond,
+            &network_name,
+        ],
+    ).await
+}
+
+pub async fn insert_churns(tx: &Transaction<'_>, network: &str, churns: &[Churn]) -> Result<u64, Error> {
+    tx.execute(
+        "INSERT INTO churns (network, date, height) 
+        SELECT $3, * FROM UNNEST($1::text[], $2::text[]) 
+        ON CONFLICT (network, height) DO NOTHING ;",
+        &[
+            &column(churns, |c| &c.date),
+            &column(churns, |c| &c.height),
+            &network,
+        ],
+    ).await
 }
@@ -0,0 +1,344 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio::sync::broadcast;
+use tokio_postgres::{Client, GenericClient, Transaction};
+
+use crate::analytics::dataset;
+use crate::api::quote;
+use crate::config::env_or;
+use crate::db::{establish_listener, AppError};
+use crate::shutdown::Shutdown;
+
+/// Postgres channel the ingesters announce what they did on.
+pub const EVENTS_CHANNEL: &str = "ingestion_events";
+
+/// Live updates buffered per subscriber before it starts missing some.
+const UPDATES_CAPACITY: usize = 1024;
+
+const DEFAULT_EVENTS_BUFFER_SIZE: usize = 1000;
+
+/// Length of the intervals the ingesters store.
+const INTERVAL_SECS: i64 = 3600;
+
+/// What the ingesters announce, possibly from another process than the server.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", rename_all_fields = "camelCase")]
+pub enum IngestionEvent {
+    /// Intervals of one network's dataset (and pool) were committed, by `end_time` range
+    IntervalsCommitted {
+        network: String,
+        dataset: String,
+        pool: Option<String>,
+        from_end_time: i64,
+        to_end_time: i64,
+    },
+    /// A feed that was more than one interval behind has been caught up
+    GapRepaired {
+        network: String,
+        dataset: String,
+        pool: Option<String>,
+        from_end_time: i64,
+        to_end_time: i64,
+        intervals: u64,
+    },
+    IngesterError {
+        network: String,
+        source: String,
+        error: String,
+    },
+}
+
+/// An event as sent on `/events`, `id` increases by one per event.
+#[derive(Debug, Clone)]
+pub struct LoggedEvent {
+    pub id: u64,
+    pub name: &'static str,
+    pub data: Value,
+}
+
+/// The last `EVENTS_BUFFER_SIZE` events, so reconnecting clients can catch up.
+pub struct EventLog {
+    buffer: Mutex<(u64, VecDeque<LoggedEvent>)>,
+    /// Ids of this log start after the boot time in the upper 32 bits, so an id handed out
+    /// before a restart, or by another replica, is told apart from this log's.
+    first_id: u64,
+    capacity: usize,
+    live: broadcast::Sender<LoggedEvent>,
+}
+
+impl EventLog {
+    fn new(capacity: usize) -> EventLog {
+        let (live, _) = broadcast::channel(UPDATES_CAPACITY);
+        let first_id = (Utc::now().timestamp().max(0) as u64) << 32;
+        EventLog {
+            buffer: Mutex::new((first_id, VecDeque::with_capacity(capacity))),
+            first_id,
+            capacity,
+            live,
+        }
+    }
+
+    fn push(&self, name: &'static str, data: Value) {
+        let mut buffer = self.buffer.lock().unwrap();
+        let (next_id, events) = &mut *buffer;
+        *next_id += 1;
+        let event = LoggedEvent { id: *next_id, name, data };
+
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back(event.clone());
+        // Sent under the lock, so `replay_since` never misses or repeats an event
+        let _ = self.live.send(event);
+    }
+
+    /// The buffered events after `last_event_id`, a receiver for the ones after them and the
+    /// id of the last event the client then has. A new subscriber, without an id, only gets
+    /// the events to come. An id this log did not hand out, such as one from before a
+    /// restart of the server, replays the whole buffer.
+    pub fn replay_since(&self, last_event_id: Option<u64>) -> (Vec<LoggedEvent>, broadcast::Receiver<LoggedEvent>, u64) {
+        let buffer = self.buffer.lock().unwrap();
+        let (last_id, events) = &*buffer;
+        let after = match last_event_id {
+            None => *last_id,
+            Some(id) if (self.first_id..=*last_id).contains(&id) => id,
+            Some(_) => self.first_id,
+        };
+        let replay: Vec<LoggedEvent> = events.iter().filter(|event| event.id > after).cloned().collect();
+        let last_sent = replay.last().map(|event| event.id).unwrap_or(after);
+
+        (replay, self.live.subscribe(), last_sent)
+    }
+}
+
+/// A stored interval as sent to stream subscribers, `data` is its row in camelCase.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntervalUpdate {
+    pub network: String,
+    pub dataset: String,
+    pub pool: Option<String>,
+    pub end_time: i64,
+    pub data: Value,
+}
+
+/// Streams shared by the server's handlers.
+#[derive(Clone)]
+pub struct Events {
+    pub intervals: broadcast::Sender<IntervalUpdate>,
+    pub log: Arc<EventLog>,
+    pub shutdown: Shutdown,
+}
+
+impl Events {
+    /// Starts relaying the ingesters' notifications, possibly from another process, to subscribers.
+    pub fn start(shutdown: Shutdown) -> Events {
+        let (intervals, _) = broadcast::channel(UPDATES_CAPACITY);
+        let log = Arc::new(EventLog::new(env_or("EVENTS_BUFFER_SIZE", DEFAULT_EVENTS_BUFFER_SIZE).max(1)));
+        let events = Events { intervals, log, shutdown };
+        tokio::spawn(relay_events(events.clone()));
+
+        events
+    }
+}
+
+/// Announces `event` to the server's subscribers, on commit when `client` is a transaction.
+pub async fn publish(client: &impl GenericClient, event: &IngestionEvent) -> Result<(), AppError> {
+    let payload = serde_json::to_string(event)?;
+    client.execute("SELECT pg_notify($1, $2)", &[&EVENTS_CHANNEL, &payload]).await?;
+    Ok(())
+}
+
+/// Announces an ingester failure, only logging when that fails too.
+pub async fn publish_error(client: &Client, network: &str, source: &str, error: &impl Display) {
+    let event = IngestionEvent::IngesterError { network: network.to_string(), source: source.to_string(), error: error.to_string() };
+    if let Err(e) = publish(client, &event).await {
+        eprintln!("Failed to publish {} {} error: {}", network, source, e);
+    }
+}
+
+/// Announces the intervals an insert stored, by `end_time`, to the stream subscribers once
+/// `tx` commits. Each run of consecutive hours is one event, so relaying an event never
+/// reads back intervals that were stored before.
+pub async fn notify_intervals(tx: &Transaction<'_>, network: &str, dataset: &str, pool: Option<&str>, end_times: &[i64]) -> Result<(), AppError> {
+    let mut end_times = end_times.to_vec();
+    end_times.sort_unstable();
+    let mut runs: Vec<(i64, i64)> = Vec::new();
+    for end_time in end_times {
+        match runs.last_mut() {
+            Some((_, to_end_time)) if end_time == *to_end_time + INTERVAL_SECS => *to_end_time = end_time,
+            _ => runs.push((end_time, end_time)),
+        }
+    }
+
+    for (from_end_time, to_end_time) in runs {
+        let event = IngestionEvent::IntervalsCommitted {
+            network: network.to_string(),
+            dataset: dataset.to_string(),
+            pool: pool.map(str::to_string),
+            from_end_time,
+            to_end_time,
+        };
+        publish(tx, &event).await?;
+    }
+    Ok(())
+}
+
+/// Reads stored intervals of a network's dataset with `after < end_time <= until`, oldest first.
+pub async fn fetch_interval_updates(client: &Client, network: &str, dataset_name: &str, pool: Option<&str>, after: i64, until: i64, limit: i64) -> Result<Vec<IntervalUpdate>, AppError> {
+    let Some(dataset) = dataset(dataset_name) else {
+        return Ok(Vec::new());
+    };
+    let pool = dataset.default_pool.map(|default| pool.unwrap_or(default));
+    let pool_filter = pool.map(|pool| format!("pool = {} AND", quote(pool))).unwrap_or_default();
+
+    let query = format!(
+        "SELECT end_time::bigint AS end_time, row_to_json(t)::text AS data FROM (SELECT * FROM {}) t
+        WHERE network = {} AND {} end_time::bigint > $1 AND end_time::bigint <= $2 ORDER BY end_time::bigint LIMIT $3",
+        dataset.source, quote(network), pool_filter
+    );
+    let rows = client.query(&query, &[&after, &until, &limit]).await?;
+
+    let mut updates = Vec::with_capacity(rows.len());
+    for row in rows {
+        let data: Map<String, Value> = serde_json::from_str(row.get("data"))?;
+        let data: Map<String, Value> = data
+            .into_iter()
+            .filter(|(column, _)| !matches!(column.as_str(), "id" | "interval_id" | "network" | "inserted_at"))
+            .map(|(column, value)| match (column.as_str(), value) {
+                // Stored as JSON text, served as JSON like `/tvl` does
+                ("pools_depth", Value::String(text)) => (camel_case(&column), serde_json::from_str(&text).unwrap_or(Value::String(text))),
+                (_, value) => (camel_case(&column), value),
+            })
+            .collect();
+
+        updates.push(IntervalUpdate {
+            network: network.to_string(),
+            dataset: dataset_name.to_string(),
+            pool: pool.map(str::to_string),
+            end_time: row.get("end_time"),
+            data: Value::Object(data),
+        });
+    }
+    Ok(updates)
+}
+
+/// Column name as the API spells it, e.g. `asset_price_usd` as `assetPriceUSD`.
+fn camel_case(column: &str) -> String {
+    let mut name = String::with_capacity(column.len());
+    for (i, part) in column.split('_').enumerate() {
+        match (i, part) {
+            (0, _) => name.push_str(part),
+            (_, "usd") => name.push_str("USD"),
+            _ => {
+                let mut chars = part.chars();
+                if let Some(first) = chars.next() {
+                    name.extend(first.to_uppercase());
+                    name.push_str(chars.as_str());
+                }
+            }
+        }
+    }
+    name
+}
+
+/// Forwards the ingesters' events to `/events` and committed intervals to `/ws` until a
+/// shutdown is requested, reconnecting the listening connection when it drops.
+async fn relay_events(mut events: Events) {
+    while !events.shutdown.is_requested() {
+        let (client, mut notifications) = match establish_listener(EVENTS_CHANNEL).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Failed to listen for ingestion events: {}", e);
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(5)) => {}
+                    _ = events.shutdown.wait() => {}
+                }
+                continue;
+            }
+        };
+
+        loop {
+            let notification = tokio::select! {
+                notification = notifications.recv() => notification,
+                _ = events.shutdown.wait() => return,
+            };
+            let Some(notification) = notification else {
+                break;
+            };
+            let event: IngestionEvent = match serde_json::from_str(notification.payload()) {
+                Ok(event) => event,
+                Err(e) => {
+                    eprintln!("Ignoring malformed ingestion event: {}", e);
+                    continue;
+                }
+            };
+
+            match &event {
+                IngestionEvent::IntervalsCommitted { network, dataset, pool, from_end_time, to_end_time } => {
+                    let limit = (to_end_time - from_end_time) / INTERVAL_SECS + 1;
+                    match fetch_interval_updates(&client, network, dataset, pool.as_deref(), from_end_time - 1, *to_end_time, limit).await {
+                        Ok(updates) => {
+                            for update in updates {
+                                events.log.push("interval", serde_json::to_value(&update).unwrap_or_default());
+                                // Nobody listening is not an error
+                                let _ = events.intervals.send(update);
+                            }
+                        }
+                        Err(e) => eprintln!("Failed to fetch committed {} {} intervals: {}", network, dataset, e),
+                    }
+                }
+                IngestionEvent::GapRepaired { .. } => events.log.push("gap_repaired", serde_json::to_value(&event).unwrap_or_default()),
+                IngestionEvent::IngesterError { .. } => events.log.push("ingester_error", serde_json::to_value(&event).unwrap_or_default()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log_of(events: usize) -> EventLog {
+        let log = EventLog::new(10);
+        for i in 0..events {
+            log.push("interval", Value::from(i));
+        }
+        log
+    }
+
+    fn replayed(log: &EventLog, last_event_id: Option<u64>) -> (Vec<u64>, u64) {
+        let (replay, _, last_sent) = log.replay_since(last_event_id);
+        (replay.iter().map(|event| event.id).collect(), last_sent)
+    }
+
+    #[test]
+    fn new_subscribers_only_get_the_events_to_come() {
+        let log = log_of(3);
+        let (ids, last_sent) = replayed(&log, None);
+        assert!(ids.is_empty());
+        assert_eq!(last_sent, log.first_id + 3);
+    }
+
+    #[test]
+    fn a_known_id_replays_the_events_after_it() {
+        let log = log_of(3);
+        assert_eq!(replayed(&log, Some(log.first_id + 1)), (vec![log.first_id + 2, log.first_id + 3], log.first_id + 3));
+        assert_eq!(replayed(&log, Some(log.first_id + 3)), (vec![], log.first_id + 3));
+    }
+
+    #[test]
+    fn an_id_from_a_previous_boot_replays_the_whole_buffer() {
+        let log = log_of(3);
+        let previous_boot = (log.first_id >> 32).saturating_sub(60) << 32;
+        let (ids, last_sent) = replayed(&log, Some(previous_boot + 5));
+        assert_eq!(ids, vec![log.first_id + 1, log.first_id + 2, log.first_id + 3]);
+        assert_eq!(last_sent, log.first_id + 3);
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use async_graphql::{
+    connection::{Connection, Edge},
+    http::GraphiQLSource,
+    EmptyMutation, EmptySubscription, Object, OutputType, Result, Schema,
+};
+use axum::response::Html;
+use tokio_postgres::Row;
+
+use crate::api::{depth_interval_from_row, quote, rune_pool_interval_from_row, swaps_interval_from_row};
+use crate::config::{env_or, DEFAULT_NETWORK};
+use crate::db::establish_connection;
+use crate::model::{DepthInterval, EarningInterval, Pool, RunePoolInterval, SwapsInterval, ALL_POOLS};
+
+pub type MidgardSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+const DEFAULT_PAGE_SIZE: i32 = 100;
+const MAX_PAGE_SIZE: i32 = 400;
+
+const DEFAULT_MAX_DEPTH: usize = 10;
+const DEFAULT_MAX_COMPLEXITY: usize = 5000;
+
+/// The schema served at `/graphql`, rejecting queries nested deeper than `GRAPHQL_MAX_DEPTH`
+/// or costlier than `GRAPHQL_MAX_COMPLEXITY`, a page costing `first` times its fields.
+pub fn build_schema() -> MidgardSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .limit_depth(env_or("GRAPHQL_MAX_DEPTH", DEFAULT_MAX_DEPTH))
+        .limit_complexity(env_or("GRAPHQL_MAX_COMPLEXITY", DEFAULT_MAX_COMPLEXITY))
+        .finish()
+}
+
+pub async fn graphiql() -> Html<String> {
+    Html(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+fn page_size(first: Option<i32>) -> i32 {
+    first.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+}
+
+pub struct QueryRoot;
+
+/// Every list is a connection ordered by `endTime` oldest first, its cursor being the
+/// `endTime` of an interval: pass a page's `endCursor` as `after` for the next one.
+/// `from`/`to` bound `startTime` and `endTime` in seconds since the epoch, `network`
+/// is mainnet unless given.
+#[Object]
+// A resolver's arguments are those of its GraphQL field
+#[allow(clippy::too_many_arguments)]
+impl QueryRoot {
+    /// Depth and price history of a pool, BTC.BTC by default.
+    #[graphql(complexity = "page_size(first) as usize * child_complexity")]
+    async fn depth_intervals(
+        &self,
+        network: Option<String>,
+        pool: Option<String>,
+        from: Option<i64>,
+        to: Option<i64>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Connection<String, DepthInterval>> {
+        let pool = pool.unwrap_or_else(|| "BTC.BTC".to_string());
+        let series = Series { network: network.as_deref().unwrap_or(DEFAULT_NETWORK), pool: Some(&pool) };
+        fetch_page("depth_intervals", series, from, to, first, after, depth_interval_from_row).await
+    }
+
+    /// Swap history of a pool, the whole network by default.
+    #[graphql(complexity = "page_size(first) as usize * child_complexity")]
+    async fn swaps_intervals(
+        &self,
+        network: Option<String>,
+        pool: Option<String>,
+        from: Option<i64>,
+        to: Option<i64>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Connection<String, SwapsInterval>> {
+        let pool = pool.unwrap_or_else(|| ALL_POOLS.to_string());
+        let series = Series { network: network.as_deref().unwrap_or(DEFAULT_NETWORK), pool: Some(&pool) };
+        fetch_page("swap_history_intervals", series, from, to, first, after, swaps_interval_from_row).await
+    }
+
+    /// Earnings history with the earnings of each pool, only `pool`'s when given.
+    #[graphql(complexity = "page_size(first) as usize * child_complexity")]
+    async fn earning_intervals(
+        &self,
+        network: Option<String>,
+        pool: Option<String>,
+        from: Option<i64>,
+        to: Option<i64>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Connection<String, EarningInterval>> {
+        let client = establish_connection().await?;
+        let series = Series { network: network.as_deref().unwrap_or(DEFAULT_NETWORK), pool: None };
+        let (rows, has_previous, has_next) = fetch_rows(&client, "earning_intervals", series, from, to, first, after).await?;
+
+        let ids: Vec<i32> = rows.iter().map(|row| row.get("id")).collect();
+        let pool_rows = client
+            .query(
+                "SELECT * FROM pools WHERE interval_id = ANY($1) AND ($2::text IS NULL OR pool = $2) ORDER BY pool",
+                &[&ids, &pool],
+            )
+            .await?;
+        let mut pools: HashMap<i32, Vec<Pool>> = HashMap::new();
+        for row in &pool_rows {
+            pools.entry(row.get("interval_id")).or_default().push(Pool {
+                asset_liquidity_fees: row.get("asset_liquidity_fees"),
+                earnings: row.get("earnings"),
+                pool: row.get("pool"),
+                rewards: row.get("rewards"),
+                rune_liquidity_fees: row.get("rune_liquidity_fees"),
+                saver_earning: row.get("saver_earning"),
+                total_liquidity_fees_rune: row.get("total_liquidity_fees_rune"),
+            });
+        }
+
+        let mut connection = Connection::new(has_previous, has_next);
+        connection.edges.extend(rows.iter().map(|row| {
+            let interval = EarningInterval {
+                avg_node_count: row.get("avg_node_count"),
+                block_rewards: row.get("block_rewards"),
+                bonding_earnings: row.get("bonding_earnings"),
+                earnings: row.get("earnings"),
+                end_time: row.get("end_time"),
+                liquidity_earnings: row.get("liquidity_earnings"),
+                liquidity_fees: row.get("liquidity_fees"),
+                rune_price_usd: row.get("rune_price_usd"),
+                start_time: row.get("start_time"),
+                pools: pools.remove(&row.get::<_, i32>("id")).unwrap_or_default(),
+            };
+            Edge::new(interval.end_time.clone(), interval)
+        }));
+        Ok(connection)
+    }
+
+    /// RUNEPool membership history.
+    #[graphql(complexity = "page_size(first) as usize * child_complexity")]
+    async fn rune_pool_intervals(
+        &self,
+        network: Option<String>,
+        from: Option<i64>,
+        to: Option<i64>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Connection<String, RunePoolInterval>> {
+        let series = Series { network: network.as_deref().unwrap_or(DEFAULT_NETWORK), pool: None };
+        fetch_page("rune_pool_intervals", series, from, to, first, after, rune_pool_interval_from_row).await
+    }
+}
+
+/// Which rows of a table make up a series, the pool only for per-pool tables.
+struct Series<'a> {
+    network: &'a str,
+    pool: Option<&'a str>,
+}
+
+async fn fetch_page<T: OutputType>(
+    table: &str,
+    series: Series<'_>,
+    from: Option<i64>,
+    to: Option<i64>,
+    first: Option<i32>,
+    after: Option<String>,
+    from_row: fn(&Row) -> T,
+) -> Result<Connection<String, T>> {
+    let client = establish_connection().await?;
+    let (rows, has_previous, has_next) = fetch_rows(&client, table, series, from, to, first, after).await?;
+
+    let mut connection = Connection::new(has_previous, has_next);
+    connection
+        .edges
+        .extend(rows.iter().map(|row| Edge::new(row.get::<_, String>("end_time"), from_row(row))));
+    Ok(connection)
+}
+
+/// One page of `table` after the `after` cursor, with whether there are pages before and after it.
+async fn fetch_rows(
+    client: &tokio_postgres::Client,
+    table: &str,
+    series: Series<'_>,
+    from: Option<i64>,
+    to: Option<i64>,
+    first: Option<i32>,
+    after: Option<String>,
+) -> Result<(Vec<Row>, bool, bool)> {
+    let after = after
+        .map(|cursor| cursor.parse::<i64>())
+        .transpose()
+        .map_err(|_| "after must be a cursor returned by a previous page")?;
+    let limit = page_size(first);
+
+    let mut filters = vec![format!("network = {}", quote(series.network))];
+    if let Some(pool) = series.pool {
+        filters.push(format!("pool = {}", quote(pool)));
+    }
+    if let Some(from) = from {
+        filters.push(format!("start_time::bigint >= {}", from));
+    }
+    if let Some(to) = to {
+        filters.push(format!("end_time::bigint <= {}", to));
+    }
+    if let Some(after) = after {
+        filters.push(format!("end_time::bigint > {}", after));
+    }
+    let filter = format!("WHERE {}", filters.join(" AND "));
+
+    // One more than asked for tells whether there is a next page
+    let query = format!("SELECT * FROM {} {} ORDER BY end_time::bigint LIMIT {}", table, filter, limit + 1);
+    let mut rows = client.query(&query, &[]).await?;
+    let has_next = rows.len() > limit as usize;
+    rows.truncate(limit as usize);
+
+    Ok((rows, after.is_some(), has_next))
+}
@@ -0,0 +1,350 @@
+use chrono::Utc;
+use tokio_postgres::{Client, Transaction};
+
+use crate::alerts::AlertEvaluator;
+use crate::config::Network;
+use crate::events::{notify_intervals, publish, publish_error, IngestionEvent};
+use crate::shutdown::Shutdown;
+use crate::midgard::MidgardClient;
+use crate::model::{ALL_POOLS, DepthInterval, Interval, LiquidityChangeInterval, SaverInterval, SwapsInterval, TvlInterval};
+use crate::db::{establish_connection, fetch_cursor, insert_depth_intervals, insert_earning_intervals, insert_liquidity_change_intervals, insert_runepool_intervals, insert_saver_intervals, insert_swaps_intervals, insert_tvl_intervals, AppError};
+
+const PAGE_SIZE: i32 = 400;
+
+/// Runs the ingestion loop of `network` until the data runs out or a shutdown is requested,
+/// announcing why when it fails.
+pub async fn run_ingester(network: &Network, midgard: MidgardClient, shutdown: Shutdown) -> Result<(), AppError> {
+    let result = ingest_intervals(network, midgard, shutdown).await;
+    if let Err(e) = &result {
+        // The failed connection may be gone, announce on a new one
+        match establish_connection().await {
+            Ok(client) => publish_error(&client, &network.name, "interval ingester", e).await,
+            Err(connect_error) => eprintln!("Failed to publish interval ingester error: {}", connect_error),
+        }
+    }
+    result
+}
+
+/// A batch that has started inserting is always allowed to finish first.
+async fn ingest_intervals(network: &Network, midgard: MidgardClient, mut shutdown: Shutdown) -> Result<(), AppError> {
+    let mut client = establish_connection().await?;
+    let alerts = AlertEvaluator::from_env()?;
+    let network = network.name.as_str();
+
+    let mut from = fetch_last_end_time(&client, network).await.unwrap_or_else(|e| {
+        println!("Failed to fetch last end_time from the database: {}", e);
+        (Utc::now().timestamp() - 3600) as i32
+    });
+
+    let count = PAGE_SIZE;
+    println!("Fetched {} end_time is: {}", network, from);
+
+    while !shutdown.is_requested() {
+        println!("Fetching row with end_time: {}", from);
+
+        // Fetch the feeds concurrently, nothing is written yet so this can be abandoned
+        let fetch = async {
+            tokio::join!(
+                midgard.fetch_depth_data(from, count),
+                midgard.fetch_swaps_data(from, count),
+                midgard.fetch_earnings_data(from, count),
+                midgard.fetch_runepool_data(from, count),
+            )
+        };
+        let (depth_data, swaps_data, earnings_data, runepool_data) = tokio::select! {
+            data = fetch => data,
+            _ = shutdown.wait() => break,
+        };
+
+        if let Some(last_interval) = depth_data.last() {
+            let last_end_time = match last_interval.end_time.parse::<i32>() {
+                Ok(time) => time,
+                Err(e) => {
+                    println!("Failed to parse last_end_time: {}", e);
+                    continue;
+                }
+            };
+
+            let current_timestamp = Utc::now().timestamp() as i32;
+            println!("Last fetched end_time: {}", last_end_time);
+            println!("Current timestamp: {}", current_timestamp);
+
+            // Calculate the difference
+            let time_difference = current_timestamp - last_end_time;
+            println!("Time difference: {}", time_difference);
+
+            // Check if the time difference is more than an hour
+            if time_difference > 3600 {
+                // Insert the whole page in one transaction so it is either fully stored or not at all
+                let tx = client.transaction().await?;
+
+                let earnings = insert_earning_intervals(&tx, network, &earnings_data).await?;
+                println!("Earnings intervals inserted successfully! ({} new)", earnings.len());
+
+                let runepool = insert_runepool_intervals(&tx, network, &runepool_data).await?;
+                println!("Rune pool intervals inserted successfully! ({} new)", runepool.len());
+
+                let depths = insert_depth_intervals(&tx, network, &depth_data).await?;
+                println!("Depth intervals inserted successfully! ({} new)", depths.len());
+
+                let swaps = insert_swaps_intervals(&tx, network, &swaps_data).await?;
+                println!("Swap intervals inserted successfully! ({} new)", swaps.len());
+
+                // Pages overlap the cursor, only the intervals that were new are announced
+                notify_intervals(&tx, network, "earnings", None, &earnings).await?;
+                notify_intervals(&tx, network, "rune", None, &runepool).await?;
+                notify_intervals(&tx, network, "depth", Some("BTC.BTC"), &depths).await?;
+                notify_intervals(&tx, network, "swap", Some(ALL_POOLS), &swaps).await?;
+
+                tx.commit().await?;
+                alerts.evaluate(&client, network).await;
+            } else {
+                // Feeds with their own cursor are caught up once per cycle, after the main feeds
+                sync_feeds(&mut client, &midgard, network, &mut shutdown).await?;
+                alerts.evaluate(&client, network).await;
+
+                println!("Last end_time is within the last hour. Sleeping...");
+                let sleep_duration = (last_end_time - current_timestamp).max(3600) as u64;
+                println!("Sleeping for {} seconds...", sleep_duration);
+                drop(client);
+                tokio::select! {
+                    _ = tokio::time::sleep(std::time::Duration::from_secs(sleep_duration)) => {}
+                    _ = shutdown.wait() => break,
+                }
+                client = establish_connection().await?;
+                continue;
+            }
+
+            // Update 'from' with the last fetched end_time
+            from = last_end_time;
+            println!("Updated 'from' to: {}", from);
+        } else {
+            println!("No data fetched. Stopping.");
+            break;
+        }
+    }
+
+    println!("{} ingester stopped.", network);
+    Ok(())
+}
+
+/// A Midgard history feed kept up to date on its own cursor, independent of the main feeds.
+trait Feed {
+    type Item: Interval;
+    const NAME: &'static str;
+    /// Name the feed is announced to stream subscribers under, as in `analytics::dataset`
+    const DATASET: &'static str;
+    /// Table holding the feed, its latest `end_time` per network (and pool, if any) is the cursor
+    const TABLE: &'static str;
+
+    async fn fetch(midgard: &MidgardClient, pool: Option<&str>, from: i32, count: i32) -> Vec<Self::Item>;
+    async fn insert(tx: &Transaction<'_>, network: &str, items: &[Self::Item]) -> Result<Vec<i64>, AppError>;
+}
+
+struct TvlFeed;
+
+impl Feed for TvlFeed {
+    type Item = TvlInterval;
+    const NAME: &'static str = "TVL";
+    const DATASET: &'static str = "tvl";
+    const TABLE: &'static str = "tvl_intervals";
+
+    async fn fetch(midgard: &MidgardClient, _pool: Option<&str>, from: i32, count: i32) -> Vec<TvlInterval> {
+        midgard.fetch_tvl_data(from, count).await
+    }
+
+    async fn insert(tx: &Transaction<'_>, network: &str, items: &[TvlInterval]) -> Result<Vec<i64>, AppError> {
+        insert_tvl_intervals(tx, network, items).await
+    }
+}
+
+struct LiquidityChangesFeed;
+
+impl Feed for LiquidityChangesFeed {
+    type Item = LiquidityChangeInterval;
+    const NAME: &'static str = "Liquidity change";
+    const DATASET: &'static str = "liquidity";
+    const TABLE: &'static str = "liquidity_change_intervals";
+
+    async fn fetch(midgard: &MidgardClient, pool: Option<&str>, from: i32, count: i32) -> Vec<LiquidityChangeInterval> {
+        midgard.fetch_liquidity_changes_data(pool.unwrap_or_default(), from, count).await
+    }
+
+    async fn insert(tx: &Transaction<'_>, network: &str, items: &[LiquidityChangeInterval]) -> Result<Vec<i64>, AppError> {
+        Ok(insert_liquidity_change_intervals(tx, network, items).await?)
+    }
+}
+
+struct PoolDepthFeed;
+
+impl Feed for PoolDepthFeed {
+    type Item = DepthInterval;
+    const NAME: &'static str = "Depth";
+    const DATASET: &'static str = "depth";
+    const TABLE: &'static str = "depth_intervals";
+
+    async fn fetch(midgard: &MidgardClient, pool: Option<&str>, from: i32, count: i32) -> Vec<DepthInterval> {
+        midgard.fetch_pool_depth_data(pool.unwrap_or_default(), from, count).await
+    }
+
+    async fn insert(tx: &Transaction<'_>, network: &str, items: &[DepthInterval]) -> Result<Vec<i64>, AppError> {
+        Ok(insert_depth_intervals(tx, network, items).await?)
+    }
+}
+
+struct PoolSwapsFeed;
+
+impl Feed for PoolSwapsFeed {
+    type Item = SwapsInterval;
+    const NAME: &'static str = "Swap";
+    const DATASET: &'static str = "swap";
+    const TABLE: &'static str = "swap_history_intervals";
+
+    async fn fetch(midgard: &MidgardClient, pool: Option<&str>, from: i32, count: i32) -> Vec<SwapsInterval> {
+        midgard.fetch_pool_swaps_data(pool.unwrap_or_default(), from, count).await
+    }
+
+    async fn insert(tx: &Transaction<'_>, network: &str, items: &[SwapsInterval]) -> Result<Vec<i64>, AppError> {
+        Ok(insert_swaps_intervals(tx, network, items).await?)
+    }
+}
+
+struct SaversFeed;
+
+impl Feed for SaversFeed {
+    type Item = SaverInterval;
+    const NAME: &'static str = "Saver";
+    const DATASET: &'static str = "savers";
+    const TABLE: &'static str = "saver_intervals";
+
+    async fn fetch(midgard: &MidgardClient, pool: Option<&str>, from: i32, count: i32) -> Vec<SaverInterval> {
+        midgard.fetch_savers_data(pool.unwrap_or_default(), from, count).await
+    }
+
+    async fn insert(tx: &Transaction<'_>, network: &str, items: &[SaverInterval]) -> Result<Vec<i64>, AppError> {
+        Ok(insert_saver_intervals(tx, network, items).await?)
+    }
+}
+
+/// Catches a feed up to the last complete hour, committing each page on its own.
+/// Catching up more than one interval is announced as a repaired gap.
+async fn sync_feed<F: Feed>(client: &mut Client, midgard: &MidgardClient, network: &str, pool: Option<&str>, shutdown: &mut Shutdown) -> Result<(), AppError> {
+    let gap_start = fetch_cursor(client, F::TABLE, network, pool).await?;
+    let mut gap_end = None;
+    let mut repaired = 0;
+
+    loop {
+        let from = fetch_cursor(client, F::TABLE, network, pool).await?;
+        let page = tokio::select! {
+            page = F::fetch(midgard, pool, from, PAGE_SIZE) => page,
+            _ = shutdown.wait() => return Ok(()),
+        };
+        let fetched = page.len();
+        let completed = completed_intervals(page);
+        if completed.is_empty() {
+            break;
+        }
+
+        let tx = client.transaction().await?;
+        let inserted = F::insert(&tx, network, &completed).await?;
+        notify_intervals(&tx, network, F::DATASET, pool, &inserted).await?;
+        tx.commit().await?;
+        println!("{} intervals inserted successfully for {} on {}! ({} new)", F::NAME, pool.unwrap_or("all pools"), network, inserted.len());
+
+        repaired += inserted.len() as u64;
+        gap_end = inserted.iter().copied().max().or(gap_end);
+
+        // A short page or one ending in the current hour means we are caught up
+        if completed.len() < fetched || fetched < PAGE_SIZE as usize {
+            break;
+        }
+    }
+
+    if let (true, Some(to_end_time)) = (repaired > 1, gap_end) {
+        let event = IngestionEvent::GapRepaired {
+            network: network.to_string(),
+            dataset: F::DATASET.to_string(),
+            pool: pool.map(str::to_string),
+            from_end_time: i64::from(gap_start),
+            to_end_time,
+            intervals: repaired,
+        };
+        publish(client, &event).await?;
+    }
+    Ok(())
+}
+
+/// `sync_feed`, logging a failure so the other feeds and pools are still synced. Only a
+/// closed connection is returned, nothing more can be synced on it.
+async fn try_sync_feed<F: Feed>(client: &mut Client, midgard: &MidgardClient, network: &str, pool: Option<&str>, shutdown: &mut Shutdown) -> Result<(), AppError> {
+    match sync_feed::<F>(client, midgard, network, pool, shutdown).await {
+        Err(e) if client.is_closed() => Err(e),
+        Err(e) => {
+            eprintln!("Failed to sync {} intervals for {} on {}: {}", F::NAME, pool.unwrap_or("all pools"), network, e);
+            Ok(())
+        }
+        Ok(()) => Ok(()),
+    }
+}
+
+/// Catches up the feeds that keep their own cursor, per pool where Midgard requires it.
+async fn sync_feeds(client: &mut Client, midgard: &MidgardClient, network: &str, shutdown: &mut Shutdown) -> Result<(), AppError> {
+    try_sync_feed::<TvlFeed>(client, midgard, network, None, shutdown).await?;
+
+    for pool in midgard.fetch_pool_assets().await {
+        if shutdown.is_requested() {
+            break;
+        }
+        try_sync_feed::<LiquidityChangesFeed>(client, midgard, network, Some(&pool), shutdown).await?;
+        try_sync_feed::<SaversFeed>(client, midgard, network, Some(&pool), shutdown).await?;
+        try_sync_feed::<PoolSwapsFeed>(client, midgard, network, Some(&pool), shutdown).await?;
+        // BTC.BTC depth is ingested with the main feeds
+        if pool != "BTC.BTC" {
+            try_sync_feed::<PoolDepthFeed>(client, midgard, network, Some(&pool), shutdown).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Drops intervals that have not finished yet, Midgard includes the current hour.
+fn completed_intervals<T: Interval>(page: Vec<T>) -> Vec<T> {
+    let now = Utc::now().timestamp();
+    page.into_iter()
+        .filter(|interval| interval.end_time().parse::<i64>().is_ok_and(|end_time| end_time <= now))
+        .collect()
+}
+
+pub async fn fetch_last_end_time(client: &Client, network: &str) -> Result<i32, tokio_postgres::Error> {
+    
+    let row = client
+        .query_opt("SELECT start_time FROM depth_intervals WHERE network = $1 AND pool = 'BTC.BTC' ORDER BY end_time DESC LIMIT 1", &[&network])
+        .await?;
+
+    println!("Row fetched: {:?}", row);
+
+    match row {
+        Some(r) => {
+            let end_time_str: String = r.get(0);
+            
+            match end_time_str.parse::<i32>() {
+                Ok(end_time) => {
+                    println!("Last end_time fetched and parsed: {}", end_time);
+                    Ok(end_time)
+                }
+                Err(e) => {
+                    println!("Failed to parse end_time: {}, using default timestamp.", e);
+                    
+                    let fallback_time = (Utc::now().timestamp() - 3600) as i32;
+                    Ok(fallback_time)
+                }
+            }
+        }
+        None => {
+            
+            println!("No rows found in depth_intervals, using default timestamp.");
+            let fallback_time = (Utc::now().timestamp() - 3600) as i32;
+            Ok(fallback_time)
+        }
+    }
+}
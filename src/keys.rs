@@ -0,0 +1,465 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration as StdDuration, Instant};
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+use tokio_postgres::{Client, Row};
+
+use crate::config::env_or;
+use crate::db::{ensure_schema, establish_connection, AppError};
+use crate::model::{ApiKey, ApiKeyUsage};
+
+const DEFAULT_RATE_PER_MINUTE: i32 = 60;
+const DEFAULT_BURST: i32 = 60;
+/// How long a looked up key is trusted, so revoking a key takes up to this long.
+const DEFAULT_KEY_CACHE_SECS: u64 = 30;
+/// How long a stream token opens `/ws` and `/events`. Long enough for an `EventSource`
+/// to reconnect with the URL it was created with.
+const DEFAULT_STREAM_TOKEN_SECS: i64 = 3600;
+
+const KEY_REQUIRED: &str = "An API key is required, send it as the X-API-Key header";
+
+const KEYS_USAGE: &str = "Usage: midgard_api_fetcher keys create <name> [--rate <per minute>] [--burst <requests>] [--quota <per day>]
+       midgard_api_fetcher keys list
+       midgard_api_fetcher keys revoke <id>
+       midgard_api_fetcher keys usage [<id>] [--days <days>]";
+
+/// Requests a key can still make right now, refilled at its rate up to its burst.
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl Bucket {
+    /// Takes a token, returning the tokens left or the seconds until one is available.
+    fn take(&mut self, rate_per_minute: i32, burst: i32) -> Result<u32, u64> {
+        let rate = f64::from(rate_per_minute.max(1)) / 60.0;
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.refilled_at).as_secs_f64() * rate).min(f64::from(burst.max(1)));
+        self.refilled_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(self.tokens as u32)
+        } else {
+            Err(((1.0 - self.tokens) / rate).ceil() as u64)
+        }
+    }
+}
+
+/// The key a request was authenticated with, for the handlers.
+#[derive(Debug, Clone)]
+pub struct Caller {
+    pub key_id: i32,
+}
+
+/// The limits of a key, as looked up by its hash.
+#[derive(Clone, Copy)]
+struct KeyLimits {
+    key_id: i32,
+    rate_per_minute: i32,
+    burst: i32,
+    daily_quota: Option<i64>,
+}
+
+/// Authenticates requests by API key when `REQUIRE_API_KEY` is set (the default), rate
+/// limiting each key. Requests share one Postgres connection, reopened when it drops,
+/// and keys are looked up at most every `KEY_CACHE_SECS`.
+///
+/// The token buckets live in the server process, so with several replicas behind a load
+/// balancer a key's rate and burst apply to each replica. Usage and daily quotas are
+/// counted in Postgres and hold across replicas.
+#[derive(Clone)]
+pub struct ApiKeys {
+    buckets: Arc<Mutex<HashMap<i32, Bucket>>>,
+    client: Arc<RwLock<Option<Arc<Client>>>>,
+    limits: Arc<Mutex<HashMap<String, (KeyLimits, Instant)>>>,
+    key_cache_ttl: StdDuration,
+    required: bool,
+}
+
+impl ApiKeys {
+    pub fn from_env() -> ApiKeys {
+        ApiKeys {
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+            client: Arc::new(RwLock::new(None)),
+            limits: Arc::new(Mutex::new(HashMap::new())),
+            key_cache_ttl: StdDuration::from_secs(env_or("KEY_CACHE_SECS", DEFAULT_KEY_CACHE_SECS)),
+            required: env_or("REQUIRE_API_KEY", true),
+        }
+    }
+
+    /// The shared connection, opened on first use and again after it closed.
+    async fn client(&self) -> Result<Arc<Client>, tokio_postgres::Error> {
+        if let Some(client) = self.client.read().await.as_ref().filter(|client| !client.is_closed()) {
+            return Ok(client.clone());
+        }
+
+        let mut shared = self.client.write().await;
+        // Another request may have reconnected while this one waited for the lock
+        if let Some(client) = shared.as_ref().filter(|client| !client.is_closed()) {
+            return Ok(client.clone());
+        }
+        let client = Arc::new(establish_connection().await?);
+        *shared = Some(client.clone());
+        Ok(client)
+    }
+
+    /// The limits of the key with `key_hash`, `None` when it is unknown or revoked.
+    async fn limits(&self, client: &Client, key_hash: &str) -> Result<Option<KeyLimits>, tokio_postgres::Error> {
+        if let Some((limits, looked_up_at)) = self.limits.lock().unwrap().get(key_hash) {
+            if looked_up_at.elapsed() < self.key_cache_ttl {
+                return Ok(Some(*limits));
+            }
+        }
+
+        let row = client
+            .query_opt("SELECT id, rate_per_minute, burst, daily_quota FROM api_keys WHERE key_hash = $1 AND NOT revoked", &[&key_hash])
+            .await?;
+        let mut cached = self.limits.lock().unwrap();
+        let Some(row) = row else {
+            cached.remove(key_hash);
+            return Ok(None);
+        };
+        let limits = KeyLimits {
+            key_id: row.get("id"),
+            rate_per_minute: row.get("rate_per_minute"),
+            burst: row.get("burst"),
+            daily_quota: row.get("daily_quota"),
+        };
+        cached.insert(key_hash.to_string(), (limits, Instant::now()));
+        Ok(Some(limits))
+    }
+}
+
+/// SHA-256 of a key, as stored.
+fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// What a request authenticates with.
+enum Credential {
+    Key(String),
+    StreamToken(String),
+}
+
+/// The key from `X-API-Key` or `Authorization: Bearer`. Keys are never read from the
+/// query string, where they would end up in logs and browser histories. Browsers cannot
+/// set headers on a WebSocket or `EventSource`, so `/ws` and `/events` also take a
+/// short-lived stream token as `token`.
+fn presented_credential<B>(request: &Request<B>) -> Option<Credential> {
+    let headers = request.headers();
+    let key = headers
+        .get("x-api-key")
+        .and_then(|value| value.to_str().ok())
+        .or_else(|| {
+            headers
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+        })
+        .map(|key| Credential::Key(key.trim().to_string()));
+    if key.is_some() || !matches!(request.uri().path(), "/ws" | "/events") {
+        return key;
+    }
+
+    request
+        .uri()
+        .query()
+        .and_then(|query| query.split('&').find_map(|pair| pair.strip_prefix("token=")))
+        .map(|token| Credential::StreamToken(token.to_string()))
+}
+
+/// Hash of the key a stream token was issued to, `None` when it is unknown or expired.
+async fn stream_token_key_hash(client: &Client, token: &str) -> Result<Option<String>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            "SELECT k.key_hash FROM stream_tokens t JOIN api_keys k ON k.id = t.key_id
+            WHERE t.token_hash = $1 AND t.expires_at::bigint > $2",
+            &[&hash_key(token), &Utc::now().timestamp()],
+        )
+        .await?;
+    Ok(row.map(|row| row.get("key_hash")))
+}
+
+fn error_response(status: StatusCode, error: &str) -> Response {
+    (status, Json(json!({ "error": error }))).into_response()
+}
+
+fn too_many_requests(error: &str, retry_after: u64) -> Response {
+    let mut response = error_response(StatusCode::TOO_MANY_REQUESTS, error);
+    response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    response
+}
+
+/// Middleware in front of every route but the homepage and the GraphiQL page.
+pub async fn require_api_key(State(keys): State<ApiKeys>, mut request: Request<Body>, next: Next<Body>) -> Response {
+    let path = request.uri().path();
+    if path == "/" || (path == "/graphql" && request.method() == Method::GET) {
+        return next.run(request).await;
+    }
+
+    let Some(credential) = presented_credential(&request) else {
+        if keys.required {
+            return error_response(StatusCode::UNAUTHORIZED, KEY_REQUIRED);
+        }
+        return next.run(request).await;
+    };
+
+    let client = match keys.client().await {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {}", e);
+            return error_response(StatusCode::SERVICE_UNAVAILABLE, "Failed to connect to database");
+        }
+    };
+    let key_hash = match credential {
+        Credential::Key(key) => hash_key(&key),
+        Credential::StreamToken(token) => match stream_token_key_hash(&client, &token).await {
+            Ok(Some(key_hash)) => key_hash,
+            Ok(None) => return error_response(StatusCode::UNAUTHORIZED, "Invalid or expired stream token"),
+            Err(e) => {
+                eprintln!("Failed to look up stream token: {}", e);
+                return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Failed to check API key");
+            }
+        },
+    };
+    let limits = match keys.limits(&client, &key_hash).await {
+        Ok(Some(limits)) => limits,
+        Ok(None) => return error_response(StatusCode::UNAUTHORIZED, "Invalid or revoked API key"),
+        Err(e) => {
+            eprintln!("Failed to look up API key: {}", e);
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Failed to check API key");
+        }
+    };
+    let KeyLimits { key_id, rate_per_minute, burst, daily_quota } = limits;
+
+    let taken = {
+        let mut buckets = keys.buckets.lock().unwrap();
+        let bucket = buckets.entry(key_id).or_insert_with(|| Bucket { tokens: f64::from(burst), refilled_at: Instant::now() });
+        bucket.take(rate_per_minute, burst)
+    };
+    let remaining = match taken {
+        Ok(remaining) => remaining,
+        Err(retry_after) => return too_many_requests("Rate limit exceeded", retry_after),
+    };
+
+    // Counted unless the day's quota is used up, in which case no row comes back
+    let now = Utc::now();
+    let counted = client
+        .query_opt(
+            "WITH usage AS (
+                INSERT INTO api_key_usage (key_id, day, requests) VALUES ($1, $2, 1)
+                ON CONFLICT (key_id, day) DO UPDATE SET requests = api_key_usage.requests + 1
+                WHERE $3::bigint IS NULL OR api_key_usage.requests < $3
+                RETURNING requests
+            ), used AS (
+                UPDATE api_keys SET last_used_at = $4 WHERE id = $1 AND EXISTS (SELECT 1 FROM usage)
+            )
+            SELECT requests FROM usage",
+            &[&key_id, &now.format("%Y-%m-%d").to_string(), &daily_quota, &now.timestamp().to_string()],
+        )
+        .await;
+    match counted {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            let tomorrow = (now + Duration::days(1)).date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+            return too_many_requests("Daily quota exceeded", (tomorrow - now).num_seconds().max(1) as u64);
+        }
+        Err(e) => {
+            eprintln!("Failed to count usage of API key {}: {}", key_id, e);
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Failed to check API key");
+        }
+    }
+
+    request.extensions_mut().insert(Caller { key_id });
+    let mut response = next.run(request).await;
+    let headers: &mut HeaderMap = response.headers_mut();
+    headers.insert("x-ratelimit-limit", HeaderValue::from(burst));
+    headers.insert("x-ratelimit-remaining", HeaderValue::from(remaining));
+    response
+}
+
+/// The calling key with its usage over the last 30 days.
+pub async fn get_usage(caller: Option<Extension<Caller>>) -> Response {
+    let Some(Extension(caller)) = caller else {
+        return error_response(StatusCode::UNAUTHORIZED, KEY_REQUIRED);
+    };
+
+    match establish_connection().await {
+        Ok(client) => {
+            let key = client.query_one("SELECT * FROM api_keys WHERE id = $1", &[&caller.key_id]).await.unwrap();
+            let usage = fetch_usage(&client, Some(caller.key_id), 30).await.unwrap();
+
+            Json(json!({ "data": { "key": api_key_from_row(&key), "usage": usage } })).into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {}", e);
+            Json(json!({ "error": "Failed to connect to database" })).into_response()
+        }
+    }
+}
+
+/// A token opening `/ws` and `/events` as the calling key for `STREAM_TOKEN_SECS`, for
+/// browsers to pass as `?token=`. Only its hash is stored, and it dies with its key.
+pub async fn create_stream_token(caller: Option<Extension<Caller>>) -> Response {
+    let Some(Extension(caller)) = caller else {
+        return error_response(StatusCode::UNAUTHORIZED, KEY_REQUIRED);
+    };
+
+    let mut secret = [0u8; 24];
+    rand::thread_rng().fill_bytes(&mut secret);
+    let token = format!("mgs_{}", hex::encode(secret));
+    let now = Utc::now().timestamp();
+    let expires_at = now + env_or("STREAM_TOKEN_SECS", DEFAULT_STREAM_TOKEN_SECS);
+
+    match establish_connection().await {
+        Ok(client) => {
+            let created = client
+                .execute(
+                    "WITH expired AS (DELETE FROM stream_tokens WHERE expires_at::bigint <= $4)
+                    INSERT INTO stream_tokens (token_hash, key_id, expires_at) VALUES ($1, $2, $3)",
+                    &[&hash_key(&token), &caller.key_id, &expires_at.to_string(), &now],
+                )
+                .await;
+            match created {
+                Ok(_) => Json(json!({ "data": { "token": token, "expiresAt": expires_at.to_string() } })).into_response(),
+                Err(e) => {
+                    eprintln!("Failed to create stream token for API key {}: {}", caller.key_id, e);
+                    Json(json!({ "error": "Failed to create stream token" })).into_response()
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {}", e);
+            Json(json!({ "error": "Failed to connect to database" })).into_response()
+        }
+    }
+}
+
+fn api_key_from_row(row: &Row) -> ApiKey {
+    ApiKey {
+        burst: row.get("burst"),
+        created_at: row.get("created_at"),
+        daily_quota: row.get("daily_quota"),
+        id: row.get("id"),
+        last_used_at: row.get("last_used_at"),
+        name: row.get("name"),
+        prefix: row.get("prefix"),
+        rate_per_minute: row.get("rate_per_minute"),
+        revoked: row.get("revoked"),
+    }
+}
+
+async fn fetch_usage(client: &Client, key_id: Option<i32>, days: i64) -> Result<Vec<ApiKeyUsage>, AppError> {
+    let since = (Utc::now() - Duration::days(days - 1)).format("%Y-%m-%d").to_string();
+    let rows = client
+        .query(
+            "SELECT * FROM api_key_usage WHERE ($1::int IS NULL OR key_id = $1) AND day >= $2 ORDER BY day DESC, key_id",
+            &[&key_id, &since],
+        )
+        .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| ApiKeyUsage { day: row.get("day"), key_id: row.get("key_id"), requests: row.get("requests") })
+        .collect())
+}
+
+/// Value of `--<name>` in `args`, exiting with the usage when it is not a number.
+fn numeric_flag<T: std::str::FromStr>(args: &[String], name: &str) -> Option<T> {
+    let position = args.iter().position(|arg| *arg == format!("--{}", name))?;
+    match args.get(position + 1).and_then(|value| value.parse().ok()) {
+        Some(value) => Some(value),
+        None => exit_with_usage(&format!("--{} needs a positive number", name)),
+    }
+}
+
+fn exit_with_usage(error: &str) -> ! {
+    eprintln!("{}", error);
+    eprintln!("{}", KEYS_USAGE);
+    std::process::exit(2);
+}
+
+/// `keys` subcommand: creates, lists and revokes API keys and prints their usage.
+pub async fn run_keys_command(args: &[String]) -> Result<(), AppError> {
+    let client = establish_connection().await?;
+    ensure_schema(&client).await?;
+
+    match args.first().map(String::as_str) {
+        Some("create") => {
+            let Some(name) = args.get(1).filter(|name| !name.starts_with("--")) else {
+                exit_with_usage("keys create needs a name");
+            };
+            let rate_per_minute: i32 = numeric_flag(args, "rate").unwrap_or(DEFAULT_RATE_PER_MINUTE);
+            let burst: i32 = numeric_flag(args, "burst").unwrap_or(DEFAULT_BURST);
+            let daily_quota: Option<i64> = numeric_flag(args, "quota");
+            if rate_per_minute < 1 || burst < 1 || daily_quota.is_some_and(|quota| quota < 1) {
+                exit_with_usage("--rate, --burst and --quota must be at least 1");
+            }
+
+            let mut secret = [0u8; 24];
+            rand::thread_rng().fill_bytes(&mut secret);
+            let key = format!("mgd_{}", hex::encode(secret));
+
+            let row = client
+                .query_one(
+                    "INSERT INTO api_keys (burst, created_at, daily_quota, key_hash, name, prefix, rate_per_minute)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *",
+                    &[&burst, &Utc::now().timestamp().to_string(), &daily_quota, &hash_key(&key), name, &key[..12].to_string(), &rate_per_minute],
+                )
+                .await?;
+            let created = api_key_from_row(&row);
+            println!("Created API key {} ({}), store it now, it cannot be shown again:", created.id, created.name);
+            println!("{}", key);
+        }
+        Some("list") => {
+            let rows = client.query("SELECT * FROM api_keys ORDER BY id", &[]).await?;
+            for key in rows.iter().map(api_key_from_row) {
+                println!(
+                    "{}\t{}\t{}...\t{}/min burst {}\tquota {}\t{}",
+                    key.id,
+                    key.name,
+                    key.prefix,
+                    key.rate_per_minute,
+                    key.burst,
+                    key.daily_quota.map(|quota| format!("{}/day", quota)).unwrap_or_else(|| "none".to_string()),
+                    if key.revoked { "revoked" } else { "active" },
+                );
+            }
+        }
+        Some("revoke") => {
+            let Some(id) = args.get(1).and_then(|id| id.parse::<i32>().ok()) else {
+                exit_with_usage("keys revoke needs a key id");
+            };
+            let revoked = client.execute("UPDATE api_keys SET revoked = TRUE WHERE id = $1", &[&id]).await?;
+            if revoked == 0 {
+                eprintln!("No API key {}", id);
+                std::process::exit(1);
+            }
+            println!("Revoked API key {}", id);
+        }
+        Some("usage") => {
+            let key_id = args.get(1).and_then(|id| id.parse::<i32>().ok());
+            let days: i64 = numeric_flag(args, "days").unwrap_or(30);
+            for usage in fetch_usage(&client, key_id, days.max(1)).await? {
+                println!("{}\t{}\t{}", usage.day, usage.key_id, usage.requests);
+            }
+        }
+        _ => exit_with_usage("Unknown keys command"),
+    }
+
+    Ok(())
+}
@@ -1,134 +1,96 @@
-use db::{establish_connection, fetch_depth_data, fetch_earnings_data, fetch_runepool_data, fetch_swaps_data, insert_depth_interval, insert_earning_interval, insert_runepool_interval, insert_swaps_interval};
+use actions::run_action_ingester;
+use futures_util::future::join_all;
+use db::{ensure_schema, establish_connection, AppError};
+use ingest::run_ingester;
+use keys::run_keys_command;
+use midgard::MidgardClient;
 use server::start_server;
-use tokio_postgres::Client;
-use chrono::Utc;
+use shutdown::Shutdown;
+use snapshot::{run_network_snapshotter, run_pool_snapshotter};
 mod server;
 mod api;
 mod model;
 mod db;
+mod ingest;
+mod midgard;
+mod shutdown;
+mod snapshot;
+mod config;
+mod actions;
+mod analytics;
+mod alerts;
+mod events;
+mod ws;
+mod sse;
+mod graphql;
+mod keys;
+mod cache;
+mod conditional;
+mod units;
+
+const USAGE: &str = "Usage: midgard_api_fetcher [serve|ingest|all|keys]";
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenv::dotenv().ok();
-    let mut client = establish_connection().await?;
 
-    // Run the server concurrently
-    let _server_task = tokio::spawn(async {
-        start_server().await;
-    });
-
-    let mut from = fetch_last_end_time(&client).await.unwrap_or_else(|e| {
-        println!("Failed to fetch last end_time from the database: {}", e);
-        (Utc::now().timestamp() - 3600) as i32
-    });
-
-    let count = 400;
-    println!("Fetched end_time is: {}", from);
-
-    loop {
-        println!("Fetching row with end_time: {}", from);
-
-        // Fetch data from the database
-        let depth_data = fetch_depth_data(from, count).await;
-        let swaps_data = fetch_swaps_data(from, count).await;
-        let earnings_data = fetch_earnings_data(from, count).await;
-        let runepool_data = fetch_runepool_data(from, count).await;
-
-        if let Some(last_interval) = depth_data.last() {
-            let last_end_time = match last_interval.end_time.parse::<i32>() {
-                Ok(time) => time,
-                Err(e) => {
-                    println!("Failed to parse last_end_time: {}", e);
-                    continue;
-                }
-            };
-
-            let current_timestamp = Utc::now().timestamp() as i32;
-            println!("Last fetched end_time: {}", last_end_time);
-            println!("Current timestamp: {}", current_timestamp);
-
-            // Calculate the difference
-            let time_difference = current_timestamp - last_end_time;
-            println!("Time difference: {}", time_difference);
-
-            // Check if the time difference is more than an hour
-            if time_difference > 3600 {
-                // Insert data into the database
-                for earning in &earnings_data {
-                    insert_earning_interval(&client, &earning).await?;
-                }
-                println!("Earnings intervals inserted successfully!");
-
-                for runepool in &runepool_data {
-                    insert_runepool_interval(&client, &runepool).await?;
-                }
-                println!("Rune pool intervals inserted successfully!");
-
-                for depth in &depth_data {
-                    insert_depth_interval(&client, &depth).await?;
+    let mode = std::env::args().nth(1).unwrap_or_else(|| "all".to_string());
+    let shutdown = Shutdown::listen();
+
+    match mode.as_str() {
+        // API only, so replicas can be scaled independently of the ingester
+        "serve" => start_server(shutdown).await,
+        // Ingester and snapshotters only
+        "ingest" => run_ingestion(shutdown).await?,
+        // Both in one process; the API keeps serving if the ingester stops
+        "all" => {
+            let ingester_shutdown = shutdown.clone();
+            let ingester = tokio::spawn(async move {
+                match run_ingestion(ingester_shutdown).await {
+                    Ok(()) => println!("Ingestion stopped."),
+                    Err(e) => eprintln!("Ingestion failed: {}, API keeps serving.", e),
                 }
-                println!("Depth intervals inserted successfully!");
-
-                for swap in &swaps_data {
-                    insert_swaps_interval(&client, &swap).await?;
-                }
-                println!("Swap intervals inserted successfully!");
-            } else {
-                println!("Last end_time is within the last hour. Sleeping...");
-                let sleep_duration = (last_end_time - current_timestamp).max(3600) as u64;
-                println!("Sleeping for {} seconds...", sleep_duration);
-                drop(client);
-                tokio::time::sleep(std::time::Duration::from_secs(sleep_duration)).await;
-                client = establish_connection().await?;
-                continue;
-            }
-
-            // Update 'from' with the last fetched end_time
-            from = last_end_time;
-            println!("Updated 'from' to: {}", from);
-        } else {
-            println!("No data fetched. Stopping.");
-            break;
+            });
+            start_server(shutdown).await;
+            // Let the current ingestion batch finish before exiting
+            let _ = ingester.await;
+        }
+        // API key management, see `keys help`
+        "keys" => {
+            let args: Vec<String> = std::env::args().skip(2).collect();
+            run_keys_command(&args).await?;
+        }
+        "help" | "-h" | "--help" => println!("{}", USAGE),
+        _ => {
+            eprintln!("Unknown mode: {}", mode);
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
         }
     }
 
     Ok(())
 }
 
-
-
-pub async fn fetch_last_end_time(client: &Client) -> Result<i32, tokio_postgres::Error> {
-    
-    let row = client
-        .query_opt("SELECT start_time FROM depth_intervals ORDER BY end_time DESC LIMIT 1", &[])
-        .await?;
-
-    println!("Row fetched: {:?}", row);
-
-    match row {
-        Some(r) => {
-            let end_time_str: String = r.get(0);
-            
-            match end_time_str.parse::<i32>() {
-                Ok(end_time) => {
-                    println!("Last end_time fetched and parsed: {}", end_time);
-                    Ok(end_time)
-                }
-                Err(e) => {
-                    println!("Failed to parse end_time: {}, using default timestamp.", e);
-                    
-                    let fallback_time = (Utc::now().timestamp() - 3600) as i32;
-                    Ok(fallback_time)
-                }
-            }
+/// Creates the schema, then runs the interval ingester, the snapshotters and the
+/// action ingester of every configured network side by side.
+async fn run_ingestion(shutdown: Shutdown) -> Result<(), AppError> {
+    ensure_schema(&establish_connection().await?).await?;
+
+    let networks = config::networks();
+    let ingestions = networks.iter().map(|network| {
+        let shutdown = shutdown.clone();
+        async move {
+            println!("Ingesting {} from {}", network.name, network.midgard_url);
+            // One client per network, so MIDGARD_MAX_CONCURRENCY bounds all of its requests
+            let midgard = MidgardClient::new(network)?;
+            let (ingested, pools, snapshots, actions) = tokio::join!(
+                run_ingester(network, midgard.clone(), shutdown.clone()),
+                run_pool_snapshotter(network, midgard.clone(), shutdown.clone()),
+                run_network_snapshotter(network, midgard.clone(), shutdown.clone()),
+                run_action_ingester(network, midgard, shutdown),
+            );
+            ingested.and(pools).and(snapshots).and(actions)
         }
-        None => {
-            
-            println!("No rows found in depth_intervals, using default timestamp.");
-            let fallback_time = (Utc::now().timestamp() - 3600) as i32;
-            Ok(fallback_time)
-        }
-    }
+    });
+    join_all(ingestions).await.into_iter().collect()
 }
-
-
@@ -0,0 +1,200 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use tokio::sync::Semaphore;
+
+use crate::config::{env_or, Network};
+use crate::db::AppError;
+use crate::model::{Action, ALL_POOLS, Churn, DepthInterval, EarningInterval, LiquidityChangeInterval, MidgardAction, MidgardNetwork, PoolSnapshot, PoolStats, RunePoolInterval, SaverInterval, SwapsInterval, TvlInterval};
+
+const DEFAULT_MAX_CONCURRENCY: usize = 4;
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+/// Most actions Midgard returns per page.
+pub const ACTIONS_PAGE_SIZE: usize = 50;
+
+/// Shared, keep-alive HTTP client for Midgard. Cheap to clone; clones share the
+/// connection pool and the concurrency limit.
+#[derive(Clone)]
+pub struct MidgardClient {
+    http: reqwest::Client,
+    base_url: String,
+    permits: Arc<Semaphore>,
+}
+
+impl MidgardClient {
+    /// Builds the client for `network`'s Midgard, limited by `MIDGARD_MAX_CONCURRENCY` and `MIDGARD_TIMEOUT_SECS`.
+    pub fn new(network: &Network) -> Result<MidgardClient, reqwest::Error> {
+        let base_url = &network.midgard_url;
+        let max_concurrency = env_or("MIDGARD_MAX_CONCURRENCY", DEFAULT_MAX_CONCURRENCY);
+        let timeout = Duration::from_secs(env_or("MIDGARD_TIMEOUT_SECS", DEFAULT_TIMEOUT_SECS));
+
+        let http = reqwest::Client::builder()
+            .user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+            .gzip(true)
+            .timeout(timeout)
+            .connect_timeout(Duration::from_secs(10))
+            .pool_idle_timeout(Duration::from_secs(90))
+            .tcp_keepalive(Duration::from_secs(60))
+            .build()?;
+
+        Ok(MidgardClient {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            permits: Arc::new(Semaphore::new(max_concurrency.max(1))),
+        })
+    }
+
+    /// GETs `path` (relative to the base URL) as JSON, waiting for a free slot first.
+    pub async fn get_json(&self, path: &str) -> Result<serde_json::Value, reqwest::Error> {
+        let _permit = self.permits.acquire().await.expect("Midgard semaphore closed");
+        let url = format!("{}{}", self.base_url, path);
+        println!("Fetching from URL: {}", url);
+
+        self.http.get(&url).send().await?.error_for_status()?.json().await
+    }
+
+    /// Fetches the `intervals` array of a history endpoint, logging failures and
+    /// returning an empty page instead.
+    async fn fetch_intervals<T: DeserializeOwned>(&self, name: &str, path: &str) -> Vec<T> {
+        let json_response = match self.get_json(path).await {
+            Ok(json) => json,
+            Err(err) => {
+                println!("Error fetching {} data: {}", name, err);
+                return Vec::new();
+            }
+        };
+
+        match serde_json::from_value(json_response["intervals"].to_owned()) {
+            Ok(output_vec) => output_vec,
+            Err(err) => {
+                println!("Error deserializing {} data: {}", name, err);
+                Vec::new()
+            }
+        }
+    }
+
+    pub async fn fetch_depth_data(&self, from: i32, count: i32) -> Vec<DepthInterval> {
+        self.fetch_pool_depth_data("BTC.BTC", from, count).await
+    }
+
+    pub async fn fetch_pool_depth_data(&self, pool: &str, from: i32, count: i32) -> Vec<DepthInterval> {
+        let path = format!("/v2/history/depths/{}?interval=hour&count={}&from={}", pool, count, from);
+        let mut intervals: Vec<DepthInterval> = self.fetch_intervals("depth", &path).await;
+        for interval in &mut intervals {
+            interval.pool = pool.to_string();
+        }
+        intervals
+    }
+
+    pub async fn fetch_swaps_data(&self, from: i32, count: i32) -> Vec<SwapsInterval> {
+        let path = format!("/v2/history/swaps?interval=hour&count={}&from={}", count, from);
+        let mut intervals: Vec<SwapsInterval> = self.fetch_intervals("swaps", &path).await;
+        for interval in &mut intervals {
+            interval.pool = ALL_POOLS.to_string();
+        }
+        intervals
+    }
+
+    pub async fn fetch_pool_swaps_data(&self, pool: &str, from: i32, count: i32) -> Vec<SwapsInterval> {
+        let path = format!("/v2/history/swaps?pool={}&interval=hour&count={}&from={}", pool, count, from);
+        let mut intervals: Vec<SwapsInterval> = self.fetch_intervals("swaps", &path).await;
+        for interval in &mut intervals {
+            interval.pool = pool.to_string();
+        }
+        intervals
+    }
+
+    pub async fn fetch_earnings_data(&self, from: i32, count: i32) -> Vec<EarningInterval> {
+        let path = format!("/v2/history/earnings?interval=hour&count={}&from={}", count, from);
+        self.fetch_intervals("earnings", &path).await
+    }
+
+    pub async fn fetch_runepool_data(&self, from: i32, count: i32) -> Vec<RunePoolInterval> {
+        let path = format!("/v2/history/runepool?interval=hour&count={}&from={}", count, from);
+        self.fetch_intervals("rune pool", &path).await
+    }
+
+    pub async fn fetch_tvl_data(&self, from: i32, count: i32) -> Vec<TvlInterval> {
+        let path = format!("/v2/history/tvl?interval=hour&count={}&from={}", count, from);
+        self.fetch_intervals("tvl", &path).await
+    }
+
+    pub async fn fetch_liquidity_changes_data(&self, pool: &str, from: i32, count: i32) -> Vec<LiquidityChangeInterval> {
+        let path = format!("/v2/history/liquidity_changes?pool={}&interval=hour&count={}&from={}", pool, count, from);
+        let mut intervals: Vec<LiquidityChangeInterval> = self.fetch_intervals("liquidity changes", &path).await;
+        for interval in &mut intervals {
+            interval.pool = pool.to_string();
+        }
+        intervals
+    }
+
+    pub async fn fetch_savers_data(&self, pool: &str, from: i32, count: i32) -> Vec<SaverInterval> {
+        let path = format!("/v2/history/savers/{}?interval=hour&count={}&from={}", pool, count, from);
+        let mut intervals: Vec<SaverInterval> = self.fetch_intervals("savers", &path).await;
+        for interval in &mut intervals {
+            interval.pool = pool.to_string();
+        }
+        intervals
+    }
+
+    /// Fetches every pool from `/v2/pools`, without stats or snapshot time.
+    pub async fn fetch_pools(&self) -> Result<Vec<PoolSnapshot>, AppError> {
+        let json_response = self.get_json("/v2/pools").await?;
+        Ok(serde_json::from_value(json_response)?)
+    }
+
+    pub async fn fetch_pool_stats(&self, asset: &str) -> Result<PoolStats, AppError> {
+        let json_response = self.get_json(&format!("/v2/pool/{}/stats", asset)).await?;
+        Ok(serde_json::from_value(json_response)?)
+    }
+
+    pub async fn fetch_network(&self) -> Result<MidgardNetwork, AppError> {
+        let json_response = self.get_json("/v2/network").await?;
+        Ok(serde_json::from_value(json_response)?)
+    }
+
+    pub async fn fetch_churns(&self) -> Result<Vec<Churn>, AppError> {
+        let json_response = self.get_json("/v2/churns").await?;
+        Ok(serde_json::from_value(json_response)?)
+    }
+
+    /// Fetches the oldest page of actions from `from_timestamp` (seconds), or the page
+    /// `prev_page_token` points to, together with the token of the following, newer, page.
+    pub async fn fetch_actions_page(&self, from_timestamp: i64, prev_page_token: Option<&str>) -> Result<(Vec<Action>, Option<String>), AppError> {
+        let path = match prev_page_token {
+            Some(token) => format!("/v2/actions?limit={}&prevPageToken={}", ACTIONS_PAGE_SIZE, token),
+            None => format!("/v2/actions?limit={}&fromTimestamp={}", ACTIONS_PAGE_SIZE, from_timestamp),
+        };
+
+        let json_response = self.get_json(&path).await?;
+        let actions: Vec<MidgardAction> = serde_json::from_value(json_response["actions"].to_owned())?;
+        let newer_page_token = json_response["meta"]["prevPageToken"]
+            .as_str()
+            .filter(|token| !token.is_empty())
+            .map(str::to_string);
+
+        Ok((actions.into_iter().map(Action::from).collect(), newer_page_token))
+    }
+
+    /// Lists the assets of all available pools.
+    pub async fn fetch_pool_assets(&self) -> Vec<String> {
+        let pools = match self.get_json("/v2/pools?status=available").await {
+            Ok(json) => json,
+            Err(err) => {
+                println!("Error fetching pools: {}", err);
+                return Vec::new();
+            }
+        };
+
+        pools
+            .as_array()
+            .map(|pools| {
+                pools
+                    .iter()
+                    .filter_map(|pool| pool["asset"].as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
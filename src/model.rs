@@ -1,17 +1,22 @@
+use async_graphql::SimpleObject;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
-#[derive(Debug, Serialize, Deserialize ,FromRow)]
+#[derive(Debug, Serialize, Deserialize, FromRow, SimpleObject)]
 #[serde(rename_all = "camelCase")]
 pub struct DepthInterval {
     pub asset_depth: String,
     pub asset_price: String,
     #[serde(rename = "assetPriceUSD")]
+    #[graphql(name = "assetPriceUSD")]
     pub asset_price_usd: String,
     pub end_time: String,
     pub liquidity_units: String,
     pub luvi: String,
     pub members_count: String,
+    /// Not part of Midgard's payload, set by the fetcher
+    #[serde(default)]
+    pub pool: String,
     pub rune_depth: String,
     pub start_time: String,
     pub synth_supply: String,
@@ -19,7 +24,10 @@ pub struct DepthInterval {
     pub units: String,
 }
 
-#[derive(Debug, Serialize, Deserialize,FromRow)]
+/// Pool under which the network-wide swap history is stored.
+pub const ALL_POOLS: &str = "all";
+
+#[derive(Debug, Serialize, Deserialize, FromRow, SimpleObject)]
 #[serde(rename_all = "camelCase")]
 pub struct SwapsInterval {
     pub average_slip: String,
@@ -29,8 +37,13 @@ pub struct SwapsInterval {
     pub from_trade_fees: String,
     pub from_trade_volume: String,
     #[serde(rename = "fromTradeVolumeUSD")]
+    #[graphql(name = "fromTradeVolumeUSD")]
     pub from_trade_volume_usd: String,
+    /// Not part of Midgard's payload, set by the fetcher ([`ALL_POOLS`] for network-wide totals)
+    #[serde(default)]
+    pub pool: String,
     #[serde(rename = "runePriceUSD")]
+    #[graphql(name = "runePriceUSD")]
     pub rune_price_usd: String,
     pub start_time: String,
     pub synth_mint_average_slip: String,
@@ -38,33 +51,38 @@ pub struct SwapsInterval {
     pub synth_mint_fees: String,
     pub synth_mint_volume: String,
     #[serde(rename = "synthMintVolumeUSD")]
+    #[graphql(name = "synthMintVolumeUSD")]
     pub synth_mint_volume_usd: String,
     pub synth_redeem_average_slip: String,
     pub synth_redeem_count: String,
     pub synth_redeem_fees: String,
     pub synth_redeem_volume: String,
     #[serde(rename = "synthRedeemVolumeUSD")]
+    #[graphql(name = "synthRedeemVolumeUSD")]
     pub synth_redeem_volume_usd: String,
     pub to_asset_average_slip: String,
     pub to_asset_count: String,
     pub to_asset_fees: String,
     pub to_asset_volume: String,
     #[serde(rename = "toAssetVolumeUSD")]
+    #[graphql(name = "toAssetVolumeUSD")]
     pub to_asset_volume_usd: String,
     pub to_rune_average_slip: String,
     pub to_rune_count: String,
     pub to_rune_fees: String,
     pub to_rune_volume: String,
     #[serde(rename = "toRuneVolumeUSD")]
+    #[graphql(name = "toRuneVolumeUSD")]
     pub to_rune_volume_usd: String,
     pub total_count: String,
     pub total_fees: String,
     pub total_volume: String,
     #[serde(rename = "totalVolumeUSD")]
+    #[graphql(name = "totalVolumeUSD")]
     pub total_volume_usd: String,
 }
 
-#[derive(Debug, Serialize, Deserialize,FromRow)]
+#[derive(Debug, Serialize, Deserialize, FromRow, SimpleObject)]
 #[serde(rename_all = "camelCase")]
 pub struct EarningInterval {
     pub avg_node_count: String,
@@ -75,12 +93,13 @@ pub struct EarningInterval {
     pub liquidity_earnings: String,
     pub liquidity_fees: String,
     #[serde(rename = "runePriceUSD")]
+    #[graphql(name = "runePriceUSD")]
     pub rune_price_usd: String,
     pub start_time: String,
     pub pools: Vec<Pool>  // Nested pools array
 }
 
-#[derive(Debug, Serialize, Deserialize,FromRow)]
+#[derive(Debug, Serialize, Deserialize, FromRow, SimpleObject)]
 #[serde(rename_all = "camelCase")]
 pub struct Pool {
     pub asset_liquidity_fees: Option<String>,
@@ -91,11 +110,527 @@ pub struct Pool {
     pub saver_earning: Option<String>,
     pub total_liquidity_fees_rune: Option<String>,
 }
+/// Open/high/low/close of a pool's asset price over one candle, in RUNE and USD,
+/// with the swap volume traded in the pool meanwhile.
 #[derive(Debug, Serialize, Deserialize,FromRow)]
 #[serde(rename_all = "camelCase")]
+pub struct Candle {
+    pub close: String,
+    #[serde(rename = "closeUSD")]
+    pub close_usd: String,
+    pub end_time: String,
+    pub high: String,
+    #[serde(rename = "highUSD")]
+    pub high_usd: String,
+    pub low: String,
+    #[serde(rename = "lowUSD")]
+    pub low_usd: String,
+    pub open: String,
+    #[serde(rename = "openUSD")]
+    pub open_usd: String,
+    pub pool: String,
+    pub start_time: String,
+    /// Swap volume traded in the candle's hours
+    pub total_volume: Option<String>,
+    #[serde(rename = "totalVolumeUSD")]
+    pub total_volume_usd: Option<String>,
+    /// Hourly swap volume averaged over the candle, each hour weighted by its share of
+    /// the RUNE depth summed over the candle's hours
+    pub volume: Option<String>,
+    #[serde(rename = "volumeUSD")]
+    pub volume_usd: Option<String>,
+}
+
+/// One pool's earnings over one earnings interval, flattened for time series.
+#[derive(Debug, Serialize, Deserialize,FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolEarningInterval {
+    pub asset_liquidity_fees: Option<String>,
+    #[serde(rename = "assetPriceUSD")]
+    pub asset_price_usd: Option<String>,
+    pub earnings: Option<String>,
+    pub end_time: String,
+    pub pool: String,
+    pub rewards: Option<String>,
+    pub rune_liquidity_fees: Option<String>,
+    #[serde(rename = "runePriceUSD")]
+    pub rune_price_usd: Option<String>,
+    pub saver_earning: Option<String>,
+    pub start_time: String,
+    pub total_liquidity_fees_rune: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, SimpleObject)]
+#[serde(rename_all = "camelCase")]
 pub struct RunePoolInterval {
     pub count: String,
     pub end_time: String,
     pub start_time: String,
     pub units: String,
 }
+
+#[derive(Debug, Serialize, Deserialize,FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct TvlInterval {
+    pub end_time: String,
+    pub pools_depth: Vec<TvlPoolDepth>,
+    #[serde(rename = "runePriceUSD")]
+    pub rune_price_usd: Option<String>,
+    pub start_time: String,
+    pub total_value_bonded: Option<String>,
+    pub total_value_locked: Option<String>,
+    #[serde(rename = "totalValueLockedUSD")]
+    pub total_value_locked_usd: Option<String>,
+    pub total_value_pooled: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TvlPoolDepth {
+    pub pool: String,
+    pub total_depth: String,
+}
+
+#[derive(Debug, Serialize, Deserialize,FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct LiquidityChangeInterval {
+    pub add_asset_liquidity_volume: String,
+    pub add_liquidity_count: String,
+    pub add_liquidity_volume: String,
+    #[serde(rename = "addLiquidityVolumeUSD")]
+    pub add_liquidity_volume_usd: String,
+    pub add_rune_liquidity_volume: String,
+    pub end_time: String,
+    pub impermanent_loss_protection_paid: Option<String>,
+    pub net: String,
+    /// Not part of the Midgard response, set by the fetcher
+    #[serde(default)]
+    pub pool: String,
+    #[serde(rename = "runePriceUSD")]
+    pub rune_price_usd: String,
+    pub start_time: String,
+    pub withdraw_asset_volume: String,
+    pub withdraw_count: String,
+    pub withdraw_rune_volume: String,
+    pub withdraw_volume: String,
+    #[serde(rename = "withdrawVolumeUSD")]
+    pub withdraw_volume_usd: String,
+}
+
+#[derive(Debug, Serialize, Deserialize,FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct SaverInterval {
+    pub end_time: String,
+    /// Not part of the Midgard response, set by the fetcher
+    #[serde(default)]
+    pub pool: String,
+    pub savers_count: String,
+    pub savers_depth: String,
+    pub savers_units: String,
+    pub start_time: String,
+}
+
+/// A pool's `/v2/pools` entry merged with its `/v2/pool/{asset}/stats`, as taken by the snapshotter.
+#[derive(Debug, Serialize, Deserialize,FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolSnapshot {
+    pub annual_percentage_rate: String,
+    pub asset: String,
+    pub asset_depth: String,
+    pub asset_price: String,
+    #[serde(rename = "assetPriceUSD")]
+    pub asset_price_usd: String,
+    pub liquidity_units: String,
+    #[serde(rename = "poolAPY")]
+    pub pool_apy: String,
+    pub rune_depth: String,
+    #[serde(rename = "saversAPR")]
+    pub savers_apr: Option<String>,
+    pub savers_depth: Option<String>,
+    pub savers_units: Option<String>,
+    /// Not part of the Midgard response, set by the snapshotter
+    #[serde(default)]
+    pub snapshot_time: String,
+    pub status: String,
+    pub synth_supply: String,
+    pub synth_units: String,
+    pub units: String,
+    pub volume24h: String,
+    #[serde(flatten)]
+    pub stats: PoolStats,
+}
+
+/// The subset of `/v2/pool/{asset}/stats` kept in pool snapshots.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolStats {
+    pub average_slip: Option<String>,
+    pub swap_count: Option<String>,
+    pub swap_volume: Option<String>,
+    pub total_fees: Option<String>,
+    pub unique_member_count: Option<String>,
+    pub unique_swapper_count: Option<String>,
+}
+
+/// Midgard `/v2/network`, only the fields kept in network snapshots.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MidgardNetwork {
+    pub active_node_count: String,
+    pub block_rewards: BlockRewards,
+    pub bond_metrics: BondMetrics,
+    #[serde(rename = "bondingAPY")]
+    pub bonding_apy: String,
+    #[serde(rename = "liquidityAPY")]
+    pub liquidity_apy: String,
+    pub next_churn_height: String,
+    pub pool_share_factor: Option<String>,
+    pub standby_node_count: String,
+    pub total_pooled_rune: String,
+    pub total_reserve: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockRewards {
+    pub block_reward: String,
+    pub bond_reward: String,
+    pub pool_reward: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BondMetrics {
+    pub total_active_bond: String,
+    pub total_standby_bond: String,
+}
+
+#[derive(Debug, Serialize, Deserialize,FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkSnapshot {
+    pub active_node_count: String,
+    pub block_reward: String,
+    pub bond_reward: String,
+    #[serde(rename = "bondingAPY")]
+    pub bonding_apy: String,
+    #[serde(rename = "liquidityAPY")]
+    pub liquidity_apy: String,
+    pub next_churn_height: String,
+    pub pool_reward: String,
+    pub pool_share_factor: Option<String>,
+    pub snapshot_time: String,
+    pub standby_node_count: String,
+    pub total_active_bond: String,
+    pub total_pooled_rune: String,
+    pub total_reserve: String,
+    pub total_standby_bond: String,
+}
+
+impl NetworkSnapshot {
+    pub fn new(network: MidgardNetwork, snapshot_time: String) -> NetworkSnapshot {
+        NetworkSnapshot {
+            active_node_count: network.active_node_count,
+            block_reward: network.block_rewards.block_reward,
+            bond_reward: network.block_rewards.bond_reward,
+            bonding_apy: network.bonding_apy,
+            liquidity_apy: network.liquidity_apy,
+            next_churn_height: network.next_churn_height,
+            pool_reward: network.block_rewards.pool_reward,
+            pool_share_factor: network.pool_share_factor,
+            snapshot_time,
+            standby_node_count: network.standby_node_count,
+            total_active_bond: network.bond_metrics.total_active_bond,
+            total_pooled_rune: network.total_pooled_rune,
+            total_reserve: network.total_reserve,
+            total_standby_bond: network.bond_metrics.total_standby_bond,
+        }
+    }
+}
+
+/// A churn from Midgard `/v2/churns`, `date` is in nanoseconds.
+#[derive(Debug, Serialize, Deserialize,FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Churn {
+    pub date: String,
+    pub height: String,
+}
+
+/// An action as returned by Midgard `/v2/actions`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MidgardAction {
+    pub date: String,
+    pub height: String,
+    #[serde(rename = "in")]
+    pub in_txs: Vec<ActionTransaction>,
+    #[serde(default)]
+    pub metadata: ActionMetadata,
+    #[serde(rename = "out")]
+    pub out_txs: Vec<ActionTransaction>,
+    pub pools: Vec<String>,
+    pub status: String,
+    #[serde(rename = "type")]
+    pub action_type: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionTransaction {
+    #[serde(default)]
+    pub address: String,
+    pub coins: Vec<Coin>,
+    #[serde(default, rename = "txID")]
+    pub tx_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Coin {
+    pub amount: String,
+    pub asset: String,
+}
+
+/// The parts of Midgard's per-type action metadata that are stored, others are ignored.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionMetadata {
+    pub swap: Option<SwapMetadata>,
+    pub add_liquidity: Option<LiquidityMetadata>,
+    pub withdraw: Option<WithdrawMetadata>,
+    pub refund: Option<RefundMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SwapMetadata {
+    pub affiliate_fee: Option<String>,
+    #[serde(default)]
+    pub is_streaming_swap: bool,
+    pub liquidity_fee: Option<String>,
+    #[serde(default)]
+    pub network_fees: Vec<Coin>,
+    pub streaming_swap_meta: Option<serde_json::Value>,
+    pub swap_slip: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LiquidityMetadata {
+    pub liquidity_units: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WithdrawMetadata {
+    pub liquidity_units: Option<String>,
+    #[serde(default)]
+    pub network_fees: Vec<Coin>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefundMetadata {
+    #[serde(default)]
+    pub network_fees: Vec<Coin>,
+}
+
+/// A stored action, flattened from `MidgardAction`. `date` is in nanoseconds.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Action {
+    #[serde(rename = "type")]
+    pub action_type: String,
+    pub addresses: Vec<String>,
+    pub affiliate_fee: Option<String>,
+    pub date: String,
+    pub height: String,
+    pub in_coins: Vec<ActionTransaction>,
+    pub is_streaming_swap: bool,
+    pub liquidity_fee: Option<String>,
+    pub liquidity_units: Option<String>,
+    pub network_fees: Vec<Coin>,
+    pub out_coins: Vec<ActionTransaction>,
+    pub pools: Vec<String>,
+    pub status: String,
+    pub streaming_swap_meta: Option<serde_json::Value>,
+    pub swap_slip: Option<String>,
+    pub tx_id: String,
+}
+
+impl From<MidgardAction> for Action {
+    fn from(action: MidgardAction) -> Action {
+        let mut addresses: Vec<String> = action
+            .in_txs
+            .iter()
+            .chain(&action.out_txs)
+            .map(|tx| tx.address.clone())
+            .filter(|address| !address.is_empty())
+            .collect();
+        addresses.sort();
+        addresses.dedup();
+
+        let tx_id = action.in_txs.first().map(|tx| tx.tx_id.clone()).unwrap_or_default();
+        let ActionMetadata { swap, add_liquidity, withdraw, refund } = action.metadata;
+
+        let mut stored = Action {
+            action_type: action.action_type,
+            addresses,
+            affiliate_fee: None,
+            date: action.date,
+            height: action.height,
+            in_coins: action.in_txs,
+            is_streaming_swap: false,
+            liquidity_fee: None,
+            liquidity_units: None,
+            network_fees: Vec::new(),
+            out_coins: action.out_txs,
+            pools: action.pools,
+            status: action.status,
+            streaming_swap_meta: None,
+            swap_slip: None,
+            tx_id,
+        };
+
+        if let Some(swap) = swap {
+            stored.affiliate_fee = swap.affiliate_fee;
+            stored.is_streaming_swap = swap.is_streaming_swap;
+            stored.liquidity_fee = swap.liquidity_fee;
+            stored.network_fees = swap.network_fees;
+            stored.streaming_swap_meta = swap.streaming_swap_meta;
+            stored.swap_slip = swap.swap_slip;
+        }
+        if let Some(add_liquidity) = add_liquidity {
+            stored.liquidity_units = add_liquidity.liquidity_units;
+        }
+        if let Some(withdraw) = withdraw {
+            stored.liquidity_units = withdraw.liquidity_units;
+            stored.network_fees = withdraw.network_fees;
+        }
+        if let Some(refund) = refund {
+            stored.network_fees = refund.network_fees;
+        }
+
+        stored
+    }
+}
+
+/// A threshold rule on the latest value of a stored series, see `alerts`.
+#[derive(Debug, Clone, Serialize, Deserialize,FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertRule {
+    pub condition: String,
+    pub dataset: String,
+    pub enabled: bool,
+    pub field: String,
+    pub id: i32,
+    pub last_evaluated_at: Option<String>,
+    pub last_value: Option<String>,
+    pub name: String,
+    pub network: String,
+    pub pool: Option<String>,
+    /// `ok` or `firing`
+    pub state: String,
+    pub threshold: String,
+    pub webhook_url: Option<String>,
+    pub window_hours: i32,
+}
+
+/// Body of `POST /alerts`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewAlertRule {
+    pub condition: String,
+    pub dataset: String,
+    pub field: String,
+    pub name: String,
+    /// Defaults to mainnet
+    pub network: Option<String>,
+    pub pool: Option<String>,
+    pub threshold: String,
+    pub webhook_url: Option<String>,
+    pub window_hours: Option<i32>,
+}
+
+/// A rule changing between `firing` and `resolved`, as stored and sent to webhooks.
+#[derive(Debug, Serialize, Deserialize,FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertEvent {
+    pub baseline: Option<String>,
+    pub delivery_status: Option<String>,
+    pub end_time: String,
+    pub id: i32,
+    pub rule_id: i32,
+    pub state: String,
+    pub triggered_at: String,
+    pub value: Option<String>,
+}
+
+/// A partner's API key, without the key itself which is only shown when created.
+#[derive(Debug, Serialize, Deserialize,FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKey {
+    pub burst: i32,
+    pub created_at: String,
+    pub daily_quota: Option<i64>,
+    pub id: i32,
+    pub last_used_at: Option<String>,
+    pub name: String,
+    pub prefix: String,
+    pub rate_per_minute: i32,
+    pub revoked: bool,
+}
+
+/// Requests made with a key on one UTC day.
+#[derive(Debug, Serialize, Deserialize,FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyUsage {
+    pub day: String,
+    pub key_id: i32,
+    pub requests: i64,
+}
+
+/// A Midgard history interval, used by the ingester to track its cursor.
+pub trait Interval {
+    fn end_time(&self) -> &str;
+}
+
+impl Interval for EarningInterval {
+    fn end_time(&self) -> &str {
+        &self.end_time
+    }
+}
+
+impl Interval for RunePoolInterval {
+    fn end_time(&self) -> &str {
+        &self.end_time
+    }
+}
+
+impl Interval for DepthInterval {
+    fn end_time(&self) -> &str {
+        &self.end_time
+    }
+}
+
+impl Interval for SwapsInterval {
+    fn end_time(&self) -> &str {
+        &self.end_time
+    }
+}
+
+impl Interval for TvlInterval {
+    fn end_time(&self) -> &str {
+        &self.end_time
+    }
+}
+
+impl Interval for LiquidityChangeInterval {
+    fn end_time(&self) -> &str {
+        &self.end_time
+    }
+}
+
+impl Interval for SaverInterval {
+    fn end_time(&self) -> &str {
+        &self.end_time
+    }
+}
@@ -1,20 +1,68 @@
-use axum::{routing::get, Router};
+use async_graphql_axum::GraphQL;
+use axum::{middleware, routing::{delete, get, post}, Router};
 use std::net::SocketAddr;
 
-use crate::api::{get_depth_history, get_earning_history, get_rune_pool_history, get_swaps_history, show_homepage};
-pub async fn start_server() {
+use crate::api::{get_actions, get_candles, get_depth_history, get_earning_history, get_latest_pools, get_network_history, get_pool_earning_history, get_pool_snapshots, get_liquidity_history, get_rune_pool_history, get_savers_history, get_swaps_history, get_tvl_history, show_homepage};
+use crate::alerts::{create_alert_rule, delete_alert_rule, get_alert_events, get_alert_rules};
+use crate::analytics::{get_indicator, get_lp_performance, get_pool_apy};
+use crate::cache::{cache_responses, ResponseCache};
+use crate::conditional::conditional_get;
+use crate::events::Events;
+use crate::graphql::{build_schema, graphiql};
+use crate::keys::{create_stream_token, get_usage, require_api_key, ApiKeys};
+use crate::shutdown::Shutdown;
+use crate::sse::sse_handler;
+use crate::units::convert_units;
+use crate::ws::ws_handler;
+
+pub async fn start_server(mut shutdown: Shutdown) {
+    let events = Events::start(shutdown.clone());
+    let cache = ResponseCache::start(&events);
+
     let app = Router::new()  
         .route("/", get(show_homepage))
         .route("/depth", get(get_depth_history))
         .route("/swap",get(get_swaps_history))
         .route("/earnings",get(get_earning_history))
-        .route("/rune",get(get_rune_pool_history));
+        .route("/rune",get(get_rune_pool_history))
+        .route("/tvl",get(get_tvl_history))
+        .route("/liquidity",get(get_liquidity_history))
+        .route("/savers",get(get_savers_history))
+        .route("/pools",get(get_latest_pools))
+        .route("/pools/:asset/snapshots",get(get_pool_snapshots))
+        .route("/actions",get(get_actions))
+        .route("/network",get(get_network_history))
+        .route("/earnings/pools/:pool",get(get_pool_earning_history))
+        .route("/analytics/apy",get(get_pool_apy))
+        .route("/analytics/lp-performance",get(get_lp_performance))
+        .route("/candles",get(get_candles))
+        .route("/analytics/indicators",get(get_indicator))
+        .route("/alerts",get(get_alert_rules).post(create_alert_rule))
+        .route("/alerts/events",get(get_alert_events))
+        .route("/alerts/:id",delete(delete_alert_rule))
+        .route("/ws",get(ws_handler))
+        .route("/events",get(sse_handler))
+        .route("/graphql",get(graphiql).post_service(GraphQL::new(build_schema())))
+        .route("/usage",get(get_usage))
+        .route("/usage/stream-token",post(create_stream_token))
+        .with_state(events)
+        // Inside the key check, so cached responses are still authenticated and counted
+        .layer(middleware::from_fn_with_state(cache, cache_responses))
+        // Outside the cache, which stores amounts in base units whatever `units` asks for
+        .layer(middleware::from_fn(convert_units))
+        // Outside the cache, so cached responses are revalidated too
+        .layer(middleware::from_fn(conditional_get))
+        .layer(middleware::from_fn_with_state(ApiKeys::from_env(), require_api_key));
 
     let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
     println!("Server running at http://{}", addr);
 
+    // Stop accepting connections on shutdown and drain in-flight requests
     axum::Server::bind(&addr)
         .serve(app.into_make_service())
+        .with_graceful_shutdown(async move { shutdown.wait().await })
         .await
         .unwrap();
-}
\ No newline at end of file
+
+    println!("Server stopped.");
+}
@@ -0,0 +1,58 @@
+use tokio::sync::watch;
+
+/// Cloneable handle that resolves once SIGINT or SIGTERM has been received.
+#[derive(Clone)]
+pub struct Shutdown {
+    receiver: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    /// Installs the signal handlers and returns a handle shared by the server and the ingester.
+    pub fn listen() -> Shutdown {
+        let (sender, receiver) = watch::channel(false);
+
+        tokio::spawn(async move {
+            wait_for_signal().await;
+            println!("Shutdown signal received, finishing in-flight work...");
+            let _ = sender.send(true);
+            // Keep the sender alive so receivers never see a closed channel
+            std::future::pending::<()>().await;
+        });
+
+        Shutdown { receiver }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    pub async fn wait(&mut self) {
+        while !*self.receiver.borrow_and_update() {
+            if self.receiver.changed().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+async fn wait_for_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("Failed to install SIGINT handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
@@ -0,0 +1,127 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use tokio::task::JoinSet;
+use tokio::time::MissedTickBehavior;
+use tokio_postgres::Client;
+
+use crate::config::{env_or, Network};
+use crate::db::{establish_connection, insert_churns, insert_network_snapshot, insert_pool_snapshots, AppError};
+use crate::events::publish_error;
+use crate::midgard::MidgardClient;
+use crate::model::NetworkSnapshot;
+use crate::shutdown::Shutdown;
+
+const DEFAULT_POOL_SNAPSHOT_INTERVAL_SECS: u64 = 300;
+const DEFAULT_NETWORK_SNAPSHOT_INTERVAL_SECS: u64 = 300;
+
+/// A ticker firing every `env_name` seconds, starting immediately.
+fn cadence(env_name: &str, default_secs: u64) -> tokio::time::Interval {
+    let mut ticker = tokio::time::interval(Duration::from_secs(env_or(env_name, default_secs).max(1)));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    ticker
+}
+
+/// Snapshots every pool of `network` each `POOL_SNAPSHOT_INTERVAL_SECS` until a shutdown is requested.
+/// A failed snapshot is logged and retried on the next tick.
+pub async fn run_pool_snapshotter(network: &Network, midgard: MidgardClient, mut shutdown: Shutdown) -> Result<(), AppError> {
+    let mut client = establish_connection().await?;
+    let network = network.name.as_str();
+
+    let mut ticker = cadence("POOL_SNAPSHOT_INTERVAL_SECS", DEFAULT_POOL_SNAPSHOT_INTERVAL_SECS);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = shutdown.wait() => break,
+        }
+
+        match take_pool_snapshot(&mut client, &midgard, network).await {
+            Ok(inserted) => println!("{} pool snapshot taken successfully! ({} pools)", network, inserted),
+            Err(e) => {
+                eprintln!("Failed to take {} pool snapshot: {}", network, e);
+                if client.is_closed() {
+                    client = establish_connection().await?;
+                }
+                publish_error(&client, network, "pool snapshotter", &e).await;
+            }
+        }
+    }
+
+    println!("{} pool snapshotter stopped.", network);
+    Ok(())
+}
+
+async fn take_pool_snapshot(client: &mut Client, midgard: &MidgardClient, network: &str) -> Result<u64, AppError> {
+    let snapshot_time = Utc::now().timestamp().to_string();
+    let mut pools = midgard.fetch_pools().await?;
+
+    // Stats are one request per pool, the client bounds how many run at once
+    let mut stats = JoinSet::new();
+    for (index, pool) in pools.iter().enumerate() {
+        let midgard = midgard.clone();
+        let asset = pool.asset.clone();
+        stats.spawn(async move { (index, midgard.fetch_pool_stats(&asset).await) });
+    }
+    while let Some(result) = stats.join_next().await {
+        let (index, pool_stats) = result.expect("Pool stats task panicked");
+        match pool_stats {
+            Ok(pool_stats) => pools[index].stats = pool_stats,
+            Err(e) => println!("Error fetching stats for {}: {}", pools[index].asset, e),
+        }
+    }
+
+    for pool in &mut pools {
+        pool.snapshot_time = snapshot_time.clone();
+    }
+
+    let tx = client.transaction().await?;
+    let inserted = insert_pool_snapshots(&tx, network, &pools).await?;
+    tx.commit().await?;
+
+    Ok(inserted)
+}
+
+/// Snapshots `network`'s `/v2/network` and records new churns each `NETWORK_SNAPSHOT_INTERVAL_SECS`
+/// until a shutdown is requested.
+pub async fn run_network_snapshotter(network: &Network, midgard: MidgardClient, mut shutdown: Shutdown) -> Result<(), AppError> {
+    let mut client = establish_connection().await?;
+    let network = network.name.as_str();
+
+    let mut ticker = cadence("NETWORK_SNAPSHOT_INTERVAL_SECS", DEFAULT_NETWORK_SNAPSHOT_INTERVAL_SECS);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = shutdown.wait() => break,
+        }
+
+        match take_network_snapshot(&mut client, &midgard, network).await {
+            Ok(churns) => println!("{} network snapshot taken successfully! ({} new churns)", network, churns),
+            Err(e) => {
+                eprintln!("Failed to take {} network snapshot: {}", network, e);
+                if client.is_closed() {
+                    client = establish_connection().await?;
+                }
+                publish_error(&client, network, "network snapshotter", &e).await;
+            }
+        }
+    }
+
+    println!("{} network snapshotter stopped.", network);
+    Ok(())
+}
+
+async fn take_network_snapshot(client: &mut Client, midgard: &MidgardClient, network_name: &str) -> Result<u64, AppError> {
+    let snapshot_time = Utc::now().timestamp().to_string();
+    let (network, churns) = tokio::join!(midgard.fetch_network(), midgard.fetch_churns());
+    let network = NetworkSnapshot::new(network?, snapshot_time);
+    let churns = churns?;
+
+    let tx = client.transaction().await?;
+    insert_network_snapshot(&tx, network_name, &network).await?;
+    let inserted = insert_churns(&tx, network_name, &churns).await?;
+    tx.commit().await?;
+
+    Ok(inserted)
+}
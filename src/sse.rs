@@ -0,0 +1,56 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::stream::{self, Stream};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::events::{Events, LoggedEvent};
+
+/// Streams ingestion events: `interval` for each committed interval, `gap_repaired` and
+/// `ingester_error`. A `Last-Event-ID` header replays the buffered events after it.
+pub async fn sse_handler(State(events): State<Events>, headers: HeaderMap) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    let (replay, live, last_sent) = events.log.replay_since(last_event_id);
+
+    let state = (replay.into_iter(), live, events.shutdown, last_sent);
+    let stream = stream::unfold(state, |(mut replay, mut live, mut shutdown, last_sent)| async move {
+        if let Some(event) = replay.next() {
+            return Some((Ok(sse_event(&event)), (replay, live, shutdown, event.id)));
+        }
+
+        loop {
+            let received = tokio::select! {
+                received = live.recv() => received,
+                // End the stream so graceful shutdown is not held up by open clients
+                _ = shutdown.wait() => return None,
+            };
+            match received {
+                // Already replayed when the buffer and the live events overlap
+                Ok(event) if event.id <= last_sent => continue,
+                Ok(event) => return Some((Ok(sse_event(&event)), (replay, live, shutdown, event.id))),
+                Err(RecvError::Lagged(skipped)) => {
+                    let lagged = Event::default().event("lagged").data(format!("{{\"skipped\":{}}}", skipped));
+                    return Some((Ok(lagged), (replay, live, shutdown, last_sent)));
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::new().interval(Duration::from_secs(15)))
+}
+
+fn sse_event(event: &LoggedEvent) -> Event {
+    Event::default()
+        .id(event.id.to_string())
+        .event(event.name)
+        .data(event.data.to_string())
+}
@@ -0,0 +1,337 @@
+use std::str::FromStr;
+
+use axum::{
+    body::{Body, Full},
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use rust_decimal::Decimal;
+use serde_json::{json, Map, Value};
+
+/// Fields in 1e8 RUNE, as the API spells them.
+const RUNE_AMOUNTS: &[&str] = &[
+    "addLiquidityVolume", "addRuneLiquidityVolume", "averagePoolValueRune", "blockReward", "blockRewards", "bondReward",
+    "bondingEarnings", "earnings", "feesAccruedRune", "feesRune", "fromTradeFees", "fromTradeVolume", "hodlValueRune",
+    "impermanentLossProtectionPaid", "liquidityEarnings", "liquidityFee", "liquidityFees", "net", "pnlRune", "poolReward",
+    "rewards", "rewardsRune", "runeAmount", "runeDepth", "runeLiquidityFees", "saverEarning", "swapVolume",
+    "synthMintFees", "synthMintVolume", "synthRedeemFees", "synthRedeemVolume", "toAssetFees", "toAssetVolume",
+    "toRuneFees", "toRuneVolume", "totalActiveBond", "totalDepth", "totalFees", "totalLiquidityFeesRune",
+    "totalPooledRune", "totalReserve", "totalStandbyBond", "totalValueBonded", "totalValueLocked", "totalValuePooled",
+    "totalVolume", "valueRune", "valueWithoutFeesRune", "volume24h", "withdrawRuneVolume", "withdrawVolume",
+];
+
+/// The asset a coin's `amount` is RUNE in, any other asset's is in 1e8 of that asset.
+const RUNE_ASSET: &str = "THOR.RUNE";
+
+/// Fields in 1e8 of the pool's asset.
+const ASSET_AMOUNTS: &[&str] = &[
+    "addAssetLiquidityVolume", "assetAmount", "assetDepth", "assetLiquidityFees", "saversDepth", "synthSupply", "valueAsset",
+    "withdrawAssetVolume",
+];
+
+/// USD values are rounded to this many places, RUNE's price derived from a pool's not being exact.
+const USD_DECIMALS: u32 = 8;
+
+/// Endpoints `units` applies to. The indicators are derived values rather than amounts. Of
+/// `/analytics/apy` only the RUNE totals the yields were derived from are amounts, and
+/// `/actions` carries no prices, so its amounts only convert to decimals. An action's
+/// `affiliateFee` is in basis points, not an amount.
+fn converts(path: &str) -> bool {
+    matches!(
+        path,
+        "/depth" | "/swap" | "/earnings" | "/rune" | "/tvl" | "/liquidity" | "/savers" | "/pools" | "/network" | "/candles"
+            | "/actions" | "/analytics/apy" | "/analytics/lp-performance" | "/graphql"
+    ) || path.starts_with("/earnings/pools/")
+        || (path.starts_with("/pools/") && path.ends_with("/snapshots"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Units {
+    Decimal,
+    Usd,
+}
+
+enum Denomination {
+    Rune,
+    Asset,
+}
+
+/// USD prices of RUNE and of the pool's asset in an interval.
+#[derive(Clone, Copy, Default)]
+struct Prices {
+    rune_usd: Option<Decimal>,
+    asset_usd: Option<Decimal>,
+}
+
+fn decimal(value: Option<&Value>) -> Option<Decimal> {
+    match value? {
+        Value::String(text) => Decimal::from_str(text).ok(),
+        Value::Number(number) => Decimal::from_str(&number.to_string()).ok(),
+        _ => None,
+    }
+}
+
+impl Prices {
+    /// The prices of `interval`, falling back to those of the interval it is nested in.
+    /// Depth intervals and candles only carry the asset's prices, RUNE's is derived from
+    /// them, a candle's from its close.
+    fn of(interval: &Map<String, Value>, outer: Prices) -> Prices {
+        let asset_usd = decimal(interval.get("assetPriceUSD")).or_else(|| decimal(interval.get("closeUSD")));
+        let asset_rune = decimal(interval.get("assetPrice")).or_else(|| decimal(interval.get("close")));
+        let rune_from_asset = match (asset_usd, asset_rune) {
+            (Some(usd), Some(rune)) if !rune.is_zero() => usd.checked_div(rune),
+            _ => None,
+        };
+
+        Prices {
+            rune_usd: decimal(interval.get("runePriceUSD")).or(rune_from_asset).or(outer.rune_usd),
+            asset_usd: asset_usd.or(outer.asset_usd),
+        }
+    }
+}
+
+/// How `field` of an object is denominated, `coin_asset` being the object's `asset` when it is a coin.
+fn denomination(path: &str, field: &str, coin_asset: Option<&str>) -> Option<Denomination> {
+    // A candle's `volume` is the swap volume traded meanwhile
+    if RUNE_AMOUNTS.contains(&field) || (path == "/candles" && field == "volume") {
+        Some(Denomination::Rune)
+    } else if let (Some(asset), "amount") = (coin_asset, field) {
+        Some(if asset == RUNE_ASSET { Denomination::Rune } else { Denomination::Asset })
+    } else if ASSET_AMOUNTS.contains(&field) {
+        Some(Denomination::Asset)
+    } else {
+        None
+    }
+}
+
+/// Rewrites the amounts of `value` and everything nested in it, as exact decimal strings.
+/// An amount that has no price in its interval becomes `null` in USD.
+fn convert(value: &mut Value, path: &str, units: Units, outer: Prices) {
+    match value {
+        Value::Array(items) => items.iter_mut().for_each(|item| convert(item, path, units, outer)),
+        Value::Object(fields) => {
+            let prices = Prices::of(fields, outer);
+            let coin_asset = fields.get("asset").and_then(Value::as_str).map(str::to_string);
+            for (field, value) in fields.iter_mut() {
+                let Some(denomination) = denomination(path, field, coin_asset.as_deref()) else {
+                    convert(value, path, units, prices);
+                    continue;
+                };
+                let Some(amount) = decimal(Some(value)) else {
+                    continue;
+                };
+
+                let whole = amount.checked_div(Decimal::from(100_000_000));
+                let converted = match (units, denomination) {
+                    (Units::Decimal, _) => whole,
+                    (Units::Usd, Denomination::Rune) => prices.rune_usd.and_then(|price| whole?.checked_mul(price)).map(|usd| usd.round_dp(USD_DECIMALS)),
+                    (Units::Usd, Denomination::Asset) => prices.asset_usd.and_then(|price| whole?.checked_mul(price)).map(|usd| usd.round_dp(USD_DECIMALS)),
+                };
+                *value = converted.map(|converted| Value::String(converted.normalize().to_string())).unwrap_or(Value::Null);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The `units` a query asks for, `None` for base units, which need no conversion.
+fn requested_units(query: Option<&str>) -> Result<Option<Units>, &'static str> {
+    match query.and_then(|query| query.split('&').find_map(|pair| pair.strip_prefix("units="))) {
+        None | Some("base") => Ok(None),
+        Some("decimal") => Ok(Some(Units::Decimal)),
+        Some("usd") => Ok(Some(Units::Usd)),
+        Some(_) => Err("units must be one of base, decimal, usd"),
+    }
+}
+
+/// Middleware serving amounts in 1e8 base units (`units=base`, the default), in whole RUNE
+/// or asset (`units=decimal`) or in USD at the interval's prices (`units=usd`). USD values
+/// Midgard reports itself, such as `totalVolumeUSD`, are left as they are.
+pub async fn convert_units(request: Request<Body>, next: Next<Body>) -> Response {
+    let path = request.uri().path().to_string();
+    if !converts(&path) {
+        return next.run(request).await;
+    }
+    let units = match requested_units(request.uri().query()) {
+        Ok(Some(units)) => units,
+        Ok(None) => return next.run(request).await,
+        Err(error) => return Json(json!({ "error": error })).into_response(),
+    };
+
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(e) => {
+            eprintln!("Failed to read response of {}: {}", path, e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let Ok(mut json) = serde_json::from_slice::<Value>(&body) else {
+        return Response::from_parts(parts, Full::from(body)).into_response();
+    };
+
+    convert(&mut json, &path, units, Prices::default());
+    let body = json.to_string();
+    parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+    Response::from_parts(parts, Full::from(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn converted(path: &str, units: Units, mut body: Value) -> Value {
+        convert(&mut body, path, units, Prices::default());
+        body
+    }
+
+    #[test]
+    fn units_are_parsed_from_the_query() {
+        assert_eq!(requested_units(None), Ok(None));
+        assert_eq!(requested_units(Some("pool=BTC.BTC")), Ok(None));
+        assert_eq!(requested_units(Some("units=base&pool=BTC.BTC")), Ok(None));
+        assert_eq!(requested_units(Some("pool=BTC.BTC&units=decimal")), Ok(Some(Units::Decimal)));
+        assert_eq!(requested_units(Some("units=usd")), Ok(Some(Units::Usd)));
+        assert!(requested_units(Some("units=sats")).is_err());
+    }
+
+    #[test]
+    fn decimal_divides_rune_and_asset_amounts_by_1e8() {
+        let body = json!({ "data": [{ "runeDepth": "250000000", "assetDepth": "1", "assetPrice": "20000.5", "units": "1000" }] });
+        assert_eq!(
+            converted("/depth", Units::Decimal, body),
+            json!({ "data": [{ "runeDepth": "2.5", "assetDepth": "0.00000001", "assetPrice": "20000.5", "units": "1000" }] })
+        );
+    }
+
+    #[test]
+    fn usd_uses_the_interval_prices() {
+        // RUNE's price is derived from the asset's, 60000 / 20000 = 3
+        let body = json!({ "data": [
+            { "runeDepth": "200000000", "assetDepth": "50000000", "assetPrice": "20000", "assetPriceUSD": "60000" },
+            { "runeDepth": "200000000", "assetDepth": "50000000" },
+        ] });
+        assert_eq!(
+            converted("/depth", Units::Usd, body),
+            json!({ "data": [
+                { "runeDepth": "6", "assetDepth": "30000", "assetPrice": "20000", "assetPriceUSD": "60000" },
+                { "runeDepth": null, "assetDepth": null },
+            ] })
+        );
+    }
+
+    #[test]
+    fn nested_arrays_use_the_prices_of_the_interval_they_are_in() {
+        let body = json!({ "data": [{
+            "earnings": "300000000",
+            "runePriceUSD": "2.5",
+            "pools": [
+                { "pool": "BTC.BTC", "rewards": "100000000", "assetLiquidityFees": "5" },
+                { "pool": "ETH.ETH", "rewards": "200000000", "runePriceUSD": "3" },
+            ],
+        }] });
+        assert_eq!(
+            converted("/earnings", Units::Usd, body),
+            json!({ "data": [{
+                "earnings": "7.5",
+                "runePriceUSD": "2.5",
+                "pools": [
+                    { "pool": "BTC.BTC", "rewards": "2.5", "assetLiquidityFees": null },
+                    { "pool": "ETH.ETH", "rewards": "6", "runePriceUSD": "3" },
+                ],
+            }] })
+        );
+    }
+
+    #[test]
+    fn candle_volume_is_rune_only_on_candles() {
+        let candle = json!({ "volume": "150000000", "totalVolume": "300000000", "volumeUSD": "12", "close": "20000" });
+        assert_eq!(
+            converted("/candles", Units::Decimal, candle.clone()),
+            json!({ "volume": "1.5", "totalVolume": "3", "volumeUSD": "12", "close": "20000" })
+        );
+        // Elsewhere `volume` is not a known amount
+        assert_eq!(converted("/swap", Units::Decimal, candle)["volume"], "150000000");
+    }
+
+    #[test]
+    fn candles_are_priced_by_their_close() {
+        // RUNE's price is 60000 / 20000 = 3
+        let candle = json!({ "volume": "150000000", "totalVolume": "300000000", "close": "20000", "closeUSD": "60000" });
+        assert_eq!(
+            converted("/candles", Units::Usd, candle),
+            json!({ "volume": "4.5", "totalVolume": "9", "close": "20000", "closeUSD": "60000" })
+        );
+    }
+
+    #[test]
+    fn pool_earnings_are_priced_by_their_interval() {
+        let interval = json!({ "data": [{
+            "rewards": "200000000", "assetLiquidityFees": "10000", "runePriceUSD": "2.5", "assetPriceUSD": "60000",
+        }] });
+        assert_eq!(
+            converted("/earnings/pools/BTC.BTC", Units::Usd, interval),
+            json!({ "data": [{ "rewards": "5", "assetLiquidityFees": "6", "runePriceUSD": "2.5", "assetPriceUSD": "60000" }] })
+        );
+    }
+
+    #[test]
+    fn action_coins_are_denominated_by_their_asset() {
+        let action = json!({
+            "affiliateFee": "20",
+            "liquidityFee": "1200000000",
+            "in": [{ "coins": [{ "amount": "100000000", "asset": "THOR.RUNE" }] }],
+            "networkFees": [{ "amount": "2000", "asset": "BTC.BTC" }],
+        });
+        assert_eq!(
+            converted("/actions", Units::Decimal, action.clone()),
+            json!({
+                "affiliateFee": "20",
+                "liquidityFee": "12",
+                "in": [{ "coins": [{ "amount": "1", "asset": "THOR.RUNE" }] }],
+                "networkFees": [{ "amount": "0.00002", "asset": "BTC.BTC" }],
+            })
+        );
+        // Actions carry no prices
+        let usd = converted("/actions", Units::Usd, action);
+        assert_eq!(usd["liquidityFee"], Value::Null);
+        assert_eq!(usd["networkFees"][0]["amount"], Value::Null);
+    }
+
+    #[test]
+    fn apy_converts_its_rune_totals_only() {
+        let apy = json!({ "data": { "feesRune": "1080000000", "rewardsRune": "900000000", "averagePoolValueRune": "400000000000000.0000", "APY": "0.0024" } });
+        assert_eq!(
+            converted("/analytics/apy", Units::Decimal, apy),
+            json!({ "data": { "feesRune": "10.8", "rewardsRune": "9", "averagePoolValueRune": "4000000", "APY": "0.0024" } })
+        );
+        let apy = json!({ "data": { "feesRune": "1080000000", "runePriceUSD": "2.5", "APY": "0.0024" } });
+        assert_eq!(
+            converted("/analytics/apy", Units::Usd, apy),
+            json!({ "data": { "feesRune": "27", "runePriceUSD": "2.5", "APY": "0.0024" } })
+        );
+        assert!(converts("/analytics/apy") && converts("/actions") && !converts("/analytics/indicators"));
+    }
+
+    #[test]
+    fn lp_performance_converts_its_amounts_but_not_its_usd_values() {
+        let interval = json!({
+            "poolShare": "0.01", "valueRune": "400000000", "valueAsset": "20000", "valueUSD": "12",
+            "runePriceUSD": "3", "assetPriceUSD": "60000",
+        });
+        assert_eq!(
+            converted("/analytics/lp-performance", Units::Usd, interval),
+            json!({
+                "poolShare": "0.01", "valueRune": "12", "valueAsset": "12", "valueUSD": "12",
+                "runePriceUSD": "3", "assetPriceUSD": "60000",
+            })
+        );
+    }
+}
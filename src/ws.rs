@@ -0,0 +1,127 @@
+use axum::{
+    extract::{ws::{Message, WebSocket, WebSocketUpgrade}, State},
+    response::Response,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::analytics::dataset;
+use crate::config::DEFAULT_NETWORK;
+use crate::db::establish_connection;
+use crate::events::{fetch_interval_updates, Events, IntervalUpdate};
+
+/// Most stored intervals replayed per subscription when resuming.
+const RESUME_LIMIT: i64 = 5000;
+
+#[derive(Deserialize)]
+struct Subscription {
+    /// Defaults to mainnet
+    network: Option<String>,
+    dataset: String,
+    pool: Option<String>,
+}
+
+/// A client message: `{"subscribe": [{"dataset": "depth", "pool": "BTC.BTC"}], "since": 1700000000}`.
+/// With `since`, stored intervals ending after it are sent before the live ones.
+#[derive(Deserialize)]
+struct SubscribeMessage {
+    subscribe: Vec<Subscription>,
+    since: Option<i64>,
+}
+
+/// Streams each interval of the subscribed datasets as the ingester commits it.
+pub async fn ws_handler(ws: WebSocketUpgrade, State(events): State<Events>) -> Response {
+    ws.on_upgrade(move |socket| stream_intervals(socket, events))
+}
+
+async fn stream_intervals(mut socket: WebSocket, mut events: Events) {
+    let mut updates = events.intervals.subscribe();
+    // Network, dataset and pool, the pool resolved to the dataset's default as the ingester announces it
+    let mut subscriptions: Vec<(String, String, Option<String>)> = Vec::new();
+
+    loop {
+        let reply = tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => subscribe(&mut socket, &mut subscriptions, &text).await,
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+            update = updates.recv() => match update {
+                Ok(update) if subscriptions.iter().any(|(network, dataset, pool)| *network == update.network && *dataset == update.dataset && *pool == update.pool) => {
+                    Some(interval_message(&update))
+                }
+                Ok(_) => continue,
+                Err(RecvError::Lagged(skipped)) => Some(json!({ "type": "lagged", "skipped": skipped })),
+                Err(RecvError::Closed) => break,
+            },
+            _ = events.shutdown.wait() => break,
+        };
+
+        if let Some(reply) = reply {
+            if socket.send(Message::Text(reply.to_string())).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    let _ = socket.close().await;
+}
+
+/// Adds the subscriptions of a client message and replays stored intervals from `since`,
+/// returning the acknowledgement or error to send.
+async fn subscribe(socket: &mut WebSocket, subscriptions: &mut Vec<(String, String, Option<String>)>, text: &str) -> Option<serde_json::Value> {
+    let message: SubscribeMessage = match serde_json::from_str(text) {
+        Ok(message) => message,
+        Err(e) => return Some(json!({ "type": "error", "error": format!("Invalid subscription: {}", e) })),
+    };
+
+    let mut added = Vec::new();
+    for subscription in message.subscribe {
+        let Some(dataset) = dataset(&subscription.dataset) else {
+            return Some(json!({ "type": "error", "error": format!("Unknown dataset: {}", subscription.dataset) }));
+        };
+        let pool = dataset.default_pool.map(|default| subscription.pool.unwrap_or_else(|| default.to_string()));
+        let network = subscription.network.unwrap_or_else(|| DEFAULT_NETWORK.to_string());
+        added.push((network, subscription.dataset, pool));
+    }
+
+    if let Some(since) = message.since {
+        let client = match establish_connection().await {
+            Ok(client) => client,
+            Err(e) => {
+                eprintln!("Failed to connect to the database: {}", e);
+                return Some(json!({ "type": "error", "error": "Failed to connect to database" }));
+            }
+        };
+        for (network, dataset, pool) in &added {
+            let updates = match fetch_interval_updates(&client, network, dataset, pool.as_deref(), since, i64::MAX, RESUME_LIMIT).await {
+                Ok(updates) => updates,
+                Err(e) => {
+                    eprintln!("Failed to replay {} intervals: {}", dataset, e);
+                    return Some(json!({ "type": "error", "error": "Failed to replay stored intervals" }));
+                }
+            };
+            for update in &updates {
+                if socket.send(Message::Text(interval_message(update).to_string())).await.is_err() {
+                    return None;
+                }
+            }
+        }
+    }
+
+    let acknowledged: Vec<serde_json::Value> = added.iter().map(|(network, dataset, pool)| json!({ "network": network, "dataset": dataset, "pool": pool })).collect();
+    subscriptions.extend(added);
+    Some(json!({ "type": "subscribed", "subscriptions": acknowledged }))
+}
+
+fn interval_message(update: &IntervalUpdate) -> serde_json::Value {
+    json!({
+        "type": "interval",
+        "network": update.network,
+        "dataset": update.dataset,
+        "pool": update.pool,
+        "endTime": update.end_time,
+        "data": update.data,
+    })
+}